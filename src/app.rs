@@ -1,8 +1,19 @@
-use anyhow::Result;
-use clap::Parser;
-use std::path::PathBuf;
-
-use crate::{parse_select, Settings, Walker};
+use anyhow::{Context, Result};
+use clap::{builder::NonEmptyStringValueParser, ArgAction, ArgGroup, Parser, Subcommand};
+use std::{
+    env, iter,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use crate::{
+    config::{self, Config},
+    interrupt,
+    journal::{self, Run},
+    parse_select, plan,
+    protected::{ProtectedPaths, DEFAULT_PROTECTED_GLOBS},
+    Console, Settings, SymlinkPolicy, Walker,
+};
 
 /// Search for a pattern in a file and display the lines that contain it.
 #[derive(Debug, Parser)]
@@ -57,11 +68,84 @@ Examples:
     $ fnr old new . --write --select 1-3
 
     If a .fnrignore file exists in the search tree, matching paths are skipped automatically
-"
+
+    Every --write run is recorded, list them and revert the last one
+
+    $ fnr history
+    $ fnr undo
+
+    Finish a --write run interrupted by Ctrl-C
+
+    $ fnr resume
+
+    To search for a pattern named like a command, put it after --
+
+    $ fnr -- undo new
+
+    Save the proposed changes to a plan file, review it, then apply exactly those changes
+
+    $ fnr old new . --save-plan changes.json
+    $ fnr apply changes.json
+
+    Edit the list of proposed changes in $EDITOR, then write the ones left
+
+    $ fnr old new . --review
+
+    Print a unified diff of the changes instead, and apply it with git or patch
+
+    $ fnr old new . --diff > changes.patch
+    $ git apply changes.patch
+
+    Fail in CI when a forbidden string is found (exit code 1, 2 on errors)
+
+    $ fnr forbidden allowed . --check
+
+    Generate a modified copy of a template tree, leaving it untouched
+
+    $ fnr PROJECT_NAME my_app template/ --output-dir my_app/
+
+    Rename, then check the project still builds, restoring every file if it does not
+
+    $ fnr old_name new_name src/ --write --after-write \"cargo check\" --revert-on-failure
+
+    Keep a record of every replacement applied, one JSON line each
+
+    $ fnr old new . --write --audit-log fnr-audit.jsonl
+
+    In scripts, fail at once instead of waiting for another run writing the same files
+
+    $ fnr old new src/ --write --lock-timeout 0
+
+    Files are searched in parallel, limit the number of threads
+
+    $ fnr old . --threads 2 // or -j 2
+
+    Skip the files ignored by git (build output, dependencies...) as well
+
+    $ fnr old . --respect-gitignore
+
+    or by default, with `respect-gitignore = true` in ~/.config/fnr/config.toml
+
+    Skip paths listed in a gitignore-style file, paths listed in ~/.config/fnr/ignore are always skipped
+
+    $ fnr old . --ignore-file ~/my-excludes --ignore-file .local-ignore
+
+    Only search the files at the top of the tree, or skip them
+
+    $ fnr old . --max-depth 1
+    $ fnr old . --min-depth 2
+",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
+    // Options only meaningful when files are written
+    group(ArgGroup::new("writing").args(["write", "interactive", "review", "output_dir"]).multiple(true))
 )]
 pub struct Options {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[arg(help = "The pattern to search for.", required = true)]
-    pub pattern: String,
+    pub pattern: Option<String>,
 
     #[arg(
         help = "The new pattern to replace the old pattern.",
@@ -71,7 +155,7 @@ pub struct Options {
     )]
     pub new_pattern: Option<String>,
 
-    #[arg(long, help = "Write changes to disk.", conflicts_with = "lookup",)]
+    #[arg(long, help = "Write changes to disk.", conflicts_with = "lookup")]
     write: bool,
 
     #[arg(long, help = "Include hidden files in the search.")]
@@ -129,17 +213,345 @@ pub struct Options {
     )]
     select: Option<Vec<String>>,
 
+    #[arg(
+        long,
+        help = "Also write files that are read-only or hard-linked outside of the search, and ignore --max-files / --max-matches.",
+        requires = "writing"
+    )]
+    force: bool,
+
+    #[arg(
+        long,
+        help = "Abort before writing if more files would be modified, unless --force.",
+        default_value_t = 500,
+        value_name = "N"
+    )]
+    max_files: usize,
+
+    #[arg(
+        long,
+        help = "Abort before writing if more matches would be replaced, unless --force.",
+        default_value_t = 5000,
+        value_name = "N"
+    )]
+    max_matches: usize,
+
+    #[arg(
+        long,
+        help = "Globs of files never written (default: lockfiles and vendored code). Without globs, protect nothing.",
+        num_args = 0..,
+        default_values = DEFAULT_PROTECTED_GLOBS,
+        hide_default_value = true,
+        value_name = "GLOB"
+    )]
+    protect: Vec<String>,
+
+    #[arg(
+        long,
+        help = "Keep the modification time of written files.",
+        requires = "writing"
+    )]
+    preserve_mtime: bool,
+
+    #[arg(
+        long,
+        help = "Copy extended attributes to written files (unix only).",
+        requires = "writing"
+    )]
+    preserve_xattrs: bool,
+
+    #[arg(
+        long,
+        help = "Save the original content of modified files with this suffix.",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ".orig",
+        value_parser = NonEmptyStringValueParser::new(),
+        value_name = "SUFFIX",
+        requires = "writing"
+    )]
+    backup: Option<String>,
+
+    #[arg(
+        long,
+        help = "Save the original content of modified files under this directory.",
+        value_name = "DIR",
+        requires = "writing"
+    )]
+    backup_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Write every modified file, or none of them if one fails.",
+        requires = "writing"
+    )]
+    transactional: bool,
+
+    #[arg(
+        long,
+        help = "Confirm every change before writing it (implies --write).",
+        conflicts_with = "lookup"
+    )]
+    interactive: bool,
+
+    #[arg(
+        long,
+        help = "Edit the proposed changes in $EDITOR, then write the ones left.",
+        conflicts_with_all = ["lookup", "write", "interactive"]
+    )]
+    review: bool,
+
+    #[arg(
+        long,
+        help = "Save the changes proposed by a dry-run to a plan file, see `fnr apply`.",
+        value_name = "FILE",
+        conflicts_with_all = ["lookup", "writing"]
+    )]
+    save_plan: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Print a unified diff of the changes instead of the default preview.",
+        conflicts_with_all = ["lookup", "writing"]
+    )]
+    diff: bool,
+
+    #[arg(
+        short = 'U',
+        long,
+        help = "Number of unchanged lines shown around changes in the diff.",
+        default_value_t = 3,
+        value_name = "LINES",
+        requires = "diff"
+    )]
+    context: usize,
+
+    #[arg(
+        long,
+        help = "List the files that would be modified and exit with 1 if any, for CI.",
+        conflicts_with_all = ["lookup", "writing", "save_plan", "diff"]
+    )]
+    check: bool,
+
+    #[arg(
+        long,
+        help = "Write modified files under this directory instead, mirroring the searched tree (implies --write).",
+        value_name = "DIR",
+        conflicts_with_all = ["lookup", "backup", "backup_dir", "transactional"]
+    )]
+    output_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Do not copy files without changes to --output-dir.",
+        requires = "output_dir"
+    )]
+    skip_unchanged: bool,
+
+    #[arg(
+        long,
+        help = "Skip symbolic links, or follow them only to search, or also to write their targets.",
+        value_enum,
+        default_value_t = SymlinkPolicy::Skip,
+        value_name = "POLICY"
+    )]
+    symlinks: SymlinkPolicy,
+
+    #[arg(
+        long,
+        help = "Fix the line endings and final newline of written files as set by .editorconfig files.",
+        requires = "writing"
+    )]
+    editorconfig: bool,
+
+    #[arg(
+        long,
+        help = "Search and write files larger than this many bytes line by line, in constant memory.",
+        value_name = "BYTES",
+        default_value_t = 64 * 1024 * 1024
+    )]
+    stream_above: u64,
+
+    #[arg(
+        long,
+        help = "Run this command once the changes are written, e.g. \"cargo check\".",
+        value_name = "CMD",
+        value_parser = NonEmptyStringValueParser::new(),
+        requires = "writing"
+    )]
+    after_write: Option<String>,
+
+    #[arg(
+        long,
+        help = "Restore every modified file if the --after-write command fails.",
+        requires = "after_write",
+        conflicts_with = "output_dir"
+    )]
+    revert_on_failure: bool,
+
+    #[arg(
+        long,
+        help = "Append every applied replacement to this file, as JSON lines.",
+        value_name = "FILE",
+        requires = "writing",
+        conflicts_with = "output_dir"
+    )]
+    audit_log: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Wait at most this many seconds for other runs writing the same files, 0 to fail at once.",
+        value_name = "SECONDS",
+        requires = "writing"
+    )]
+    lock_timeout: Option<u64>,
+
+    #[arg(
+        short = 'j',
+        long,
+        help = "Number of threads searching files, 0 for one per CPU. The output is the same.",
+        default_value_t = 0,
+        value_name = "N"
+    )]
+    threads: usize,
+
+    #[arg(
+        long,
+        help = "Skip the files ignored by .gitignore, .ignore and git excludes, as well as .fnrignore.",
+        overrides_with = "no_respect_gitignore"
+    )]
+    respect_gitignore: bool,
+
+    #[arg(
+        long,
+        help = "Search the files ignored by git, even if respect-gitignore is set in the config file.",
+        overrides_with = "respect_gitignore"
+    )]
+    no_respect_gitignore: bool,
+
+    #[arg(
+        long,
+        help = "Also skip the paths matched by this gitignore-style file (repeatable).",
+        value_name = "PATH",
+        action = ArgAction::Append
+    )]
+    ignore_file: Vec<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Descend at most N directories below the path, 1 to only search the files directly in it.",
+        value_name = "N"
+    )]
+    max_depth: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Skip the files less than N directories below the path, 2 to skip the files directly in it.",
+        value_name = "N"
+    )]
+    min_depth: Option<usize>,
+
     /// The path of the folder / file to read.
     /// Default is the current directory.
     pub path: Option<PathBuf>,
 }
 
-pub fn run() -> Result<()> {
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Restore the files modified by a --write run (the last one by default).
+    Undo {
+        /// Id of the run to revert, as listed by `fnr history`.
+        run_id: Option<String>,
+    },
+    /// List the --write runs recorded so far.
+    History,
+    /// Finish a --write run interrupted by Ctrl-C (the last one by default).
+    Resume {
+        /// Id of the run to finish, as listed by `fnr history`.
+        run_id: Option<String>,
+    },
+    /// Write the changes of a plan saved by a dry-run with --save-plan.
+    Apply {
+        /// Path of the plan file.
+        plan: PathBuf,
+        /// Append every applied replacement to this file, as JSON lines.
+        #[arg(long, value_name = "FILE")]
+        audit_log: Option<PathBuf>,
+    },
+}
+
+/// Exit codes: 0 on success, 1 when --check finds replacements to make,
+/// 2 on errors (see `main`).
+pub fn run() -> Result<ExitCode> {
     let args = Options::parse();
 
+    match args.command {
+        Some(Command::Undo { run_id }) => {
+            journal::undo(run_id, &Console::new())?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::History) => {
+            journal::history(&Console::new())?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::Apply { plan, audit_log }) => {
+            apply(&plan, audit_log, None)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::Resume { run_id }) => return resume(run_id),
+        None => {}
+    }
+
+    run_search(args, None)
+}
+
+/// `fnr apply PLAN`, or the resumption of an interrupted one
+fn apply(plan: &Path, audit_log: Option<PathBuf>, resume: Option<&Run>) -> Result<()> {
+    let settings = Settings {
+        protected: ProtectedPaths::new(DEFAULT_PROTECTED_GLOBS)?,
+        audit_log,
+        ..Settings::default()
+    };
+
+    interrupt::install_handler();
+    plan::apply(plan, settings, resume, &Console::new())
+}
+
+/// `fnr resume [RUN_ID]`: run an interrupted run again, with the same
+/// arguments and from the same directory. The files it already wrote
+/// are left as they are, and the other ones are recorded in the same run.
+fn resume(run_id: Option<String>) -> Result<ExitCode> {
+    let run = journal::resumable_run(run_id)?;
+
+    let arguments = iter::once("fnr").chain(run.info.arguments.iter().map(String::as_str));
+    let args = Options::try_parse_from(arguments)?;
+
+    if let Some(dir) = &run.info.working_directory {
+        env::set_current_dir(dir)
+            .with_context(|| format!("Could not go back to {}", dir.display()))?;
+    }
+
+    match args.command {
+        Some(Command::Apply { plan, audit_log }) => {
+            apply(&plan, audit_log, Some(&run))?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(_) => anyhow::bail!("Run {} cannot be resumed.", run.info.id),
+        // The changes edited in $EDITOR are not recorded
+        None if args.review => anyhow::bail!(
+            "Runs with --review cannot be resumed, revert it with `fnr undo {}`.",
+            run.info.id
+        ),
+        None => run_search(args, Some(run)),
+    }
+}
+
+/// Search, and replace, with the given options
+fn run_search(args: Options, resume: Option<Run>) -> Result<ExitCode> {
     // Destructure the Options struct
     // So we can use the variables directly
     let Options {
+        command: _,
         pattern,
         new_pattern,
         path,
@@ -152,10 +564,51 @@ pub fn run() -> Result<()> {
         ignored_file_types,
         write,
         select: raw_select, // Assigning variable during destructuring
+        force,
+        max_files,
+        max_matches,
+        protect,
+        preserve_mtime,
+        preserve_xattrs,
+        backup,
+        backup_dir,
+        transactional,
+        interactive,
+        save_plan,
+        review,
+        diff,
+        context,
+        check,
+        output_dir,
+        skip_unchanged,
+        symlinks,
+        editorconfig,
+        stream_above,
+        after_write,
+        revert_on_failure,
+        audit_log,
+        lock_timeout,
+        threads,
+        respect_gitignore,
+        no_respect_gitignore,
+        ignore_file,
+        max_depth,
+        min_depth,
     } = args;
 
+    let config = Config::load()?;
+
+    // These need the whole content of files
+    let stream = !(diff || save_plan.is_some() || review || output_dir.is_some() || editorconfig);
+
     let select = parse_select(raw_select)?;
 
+    if let (Some(min_depth), Some(max_depth)) = (min_depth, max_depth) {
+        if min_depth > max_depth {
+            anyhow::bail!("--min-depth {min_depth} is greater than --max-depth {max_depth}, no file would be searched.");
+        }
+    }
+
     let settings = Settings {
         verbose,
         omit_pattern: omit,
@@ -164,10 +617,50 @@ pub fn run() -> Result<()> {
         lookup,
         selected_file_types,
         ignored_file_types,
-        write,
+        write: write || interactive || output_dir.is_some(),
         select,
+        force,
+        preserve_mtime,
+        preserve_xattrs,
+        backup_suffix: backup,
+        backup_dir,
+        transactional,
+        interactive,
+        save_plan,
+        review,
+        diff,
+        diff_context: context,
+        check,
+        // Limits only apply to files written in place
+        max_files: (!force && output_dir.is_none()).then_some(max_files),
+        max_matches: (!force && output_dir.is_none()).then_some(max_matches),
+        protected: ProtectedPaths::new(&protect)?,
+        output_dir,
+        skip_unchanged,
+        symlinks,
+        editorconfig,
+        stream_above: stream.then_some(stream_above),
+        after_write,
+        revert_on_failure,
+        audit_log,
+        lock_timeout,
+        threads,
+        respect_gitignore: respect_gitignore || (config.respect_gitignore && !no_respect_gitignore),
+        // The user's ignore file comes first, with the lowest precedence
+        ignore_files: config::user_ignore_file()
+            .into_iter()
+            .chain(ignore_file)
+            .collect(),
+        min_depth,
+        max_depth,
     };
 
+    // Always present outside of subcommands
+    let pattern = pattern.unwrap_or_default();
+    if pattern.is_empty() {
+        anyhow::bail!("The pattern cannot be empty, it would match every line.");
+    }
+
     // If no path is provided, use the current directory
     let path = path.unwrap_or_else(|| PathBuf::from("."));
 
@@ -177,7 +670,15 @@ pub fn run() -> Result<()> {
         false => pattern,
     };
 
-    let walker = Walker::new(pattern, new_pattern, path, settings);
+    // Ctrl-C waits for the file being written
+    if settings.write || settings.review {
+        interrupt::install_handler();
+    }
+
+    let mut walker = Walker::new(pattern, new_pattern, path, settings);
+    if let Some(run) = resume {
+        walker = walker.resuming(run);
+    }
 
     walker.run()
 }
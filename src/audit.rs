@@ -0,0 +1,105 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::{
+    env,
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::journal;
+
+/// A replaced line, as recorded in the audit log
+#[derive(Debug, Clone)]
+pub struct LineChange {
+    /// 1-based line number
+    pub line: usize,
+    pub old: String,
+    pub new: String,
+}
+
+/// One line of the --audit-log file, for every applied replacement
+#[derive(Debug, Serialize)]
+struct AuditRecord<'a> {
+    /// RFC 3339, UTC
+    timestamp: String,
+    user: &'a str,
+    working_directory: &'a Path,
+    /// Command line of the run, program name excluded
+    arguments: &'a [String],
+    path: &'a Path,
+    line: usize,
+    old: &'a str,
+    new: &'a str,
+    hash_before: &'a str,
+    hash_after: &'a str,
+}
+
+/// Append-only log of the replacements applied by fnr, one JSON line each
+pub struct AuditLog {
+    file: File,
+    user: String,
+    working_directory: PathBuf,
+    arguments: Vec<String>,
+}
+
+impl AuditLog {
+    /// Open the log, creating it if needed, without ever truncating it
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Could not open audit log {}", path.display()))?;
+
+        let user = ["USER", "USERNAME"]
+            .into_iter()
+            .find_map(|name| env::var(name).ok())
+            .unwrap_or_else(|| "unknown".to_string());
+
+        Ok(Self {
+            file,
+            user,
+            working_directory: env::current_dir()?,
+            arguments: env::args_os()
+                .skip(1)
+                .map(|argument| argument.to_string_lossy().into_owned())
+                .collect(),
+        })
+    }
+
+    /// Record the replaced lines of a written file
+    pub fn record(
+        &mut self,
+        path: &Path,
+        changes: &[LineChange],
+        hash_before: &str,
+        hash_after: &str,
+    ) -> Result<()> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+        // Written at once, so concurrent runs never interleave partial lines
+        let mut lines = String::new();
+        for change in changes {
+            let record = AuditRecord {
+                timestamp: journal::rfc3339_timestamp(timestamp),
+                user: &self.user,
+                working_directory: &self.working_directory,
+                arguments: &self.arguments,
+                path,
+                line: change.line,
+                old: &change.old,
+                new: &change.new,
+                hash_before,
+                hash_after,
+            };
+            lines.push_str(&serde_json::to_string(&record)?);
+            lines.push('\n');
+        }
+
+        self.file
+            .write_all(lines.as_bytes())
+            .context("Could not write the audit log")
+    }
+}
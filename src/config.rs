@@ -0,0 +1,57 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::{env, fs, io, path::PathBuf};
+
+/// Name of the user's ignore file, in the config directory
+const USER_IGNORE_FILE: &str = "ignore";
+
+/// Defaults read from `config.toml` in the config directory,
+/// the command line takes precedence over them.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// Skip the files ignored by .gitignore, .ignore and git excludes
+    pub respect_gitignore: bool,
+}
+
+impl Config {
+    /// Read the config file, or the defaults if there is none
+    pub fn load() -> Result<Self> {
+        let Some(path) = config_dir().map(|dir| dir.join("config.toml")) else {
+            return Ok(Self::default());
+        };
+
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Could not read {}", path.display()));
+            }
+        };
+
+        toml::from_str(&content).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+}
+
+/// The user's ignore file (`$XDG_CONFIG_HOME/fnr/ignore`), if it exists,
+/// applied to every search
+pub fn user_ignore_file() -> Option<PathBuf> {
+    config_dir()
+        .map(|dir| dir.join(USER_IGNORE_FILE))
+        .filter(|path| path.is_file())
+}
+
+/// Directory of the user's fnr files (`$XDG_CONFIG_HOME/fnr`),
+/// `FNR_CONFIG_DIR` if set
+pub fn config_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("FNR_CONFIG_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+    if let Some(dir) = env::var_os("XDG_CONFIG_HOME") {
+        return Some(PathBuf::from(dir).join("fnr"));
+    }
+    if let Some(dir) = env::var_os("APPDATA") {
+        return Some(PathBuf::from(dir).join("fnr"));
+    }
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".config/fnr"))
+}
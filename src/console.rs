@@ -1,8 +1,11 @@
-use crate::enums::Operation;
+use crate::enums::{Answer, Operation};
+use crate::interrupt;
+use anyhow::Result;
 use colored::Colorize;
 use num_format::{Locale, ToFormattedString};
+use std::io::{self, BufRead, Write};
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct Console {}
 
 impl Console {
@@ -20,6 +23,12 @@ impl Console {
         );
     }
 
+    /// Print the reason why a file was not written
+    /// (using stderr)
+    pub fn print_skipped_file(&self, filename: &str, reason: &str) {
+        eprintln!("\nSkipped \"{}\" => {}.", filename.bold(), reason.yellow());
+    }
+
     pub fn print_lookup(
         &self,
         old_line: &str,
@@ -77,9 +86,78 @@ impl Console {
         );
     }
 
+    /// --check mode: a file that would be modified
+    pub fn print_check_file(&self, filename: &str, replacements: usize) {
+        println!(
+            "{}: {} replacement{}",
+            filename,
+            replacements,
+            if replacements > 1 { "s" } else { "" }
+        );
+    }
+
+    pub fn print_check_summary(&self, replacements: usize, files: usize) {
+        if replacements == 0 {
+            println!("{}", "No replacement to make.".green());
+            return;
+        }
+
+        println!(
+            "\n{} replacement{} to make in {} file{}.",
+            replacements.to_string().red().bold(),
+            if replacements > 1 { "s" } else { "" },
+            files,
+            if files > 1 { "s" } else { "" }
+        );
+    }
+
+    /// Print a unified diff as is, without colors,
+    /// so it can be piped to `git apply` or `patch`
+    pub fn print_diff(&self, diff: &str) {
+        print!("{diff}");
+    }
+
+    /// Ask the user whether the change printed above must be applied.
+    /// Reading the end of the input counts as quitting.
+    pub fn ask_confirmation(&self) -> Result<Answer> {
+        let stdin = io::stdin();
+
+        loop {
+            print!("  {} ", "Apply this change? [y,n,a,q,?]".blue().bold());
+            io::stdout().flush()?;
+
+            let mut input = String::new();
+            // Ctrl-C stops the run once the user answers
+            if stdin.lock().read_line(&mut input)? == 0 || interrupt::interrupted() {
+                println!();
+                return Ok(Answer::Quit);
+            }
+
+            match input.trim() {
+                "y" | "Y" => return Ok(Answer::Yes),
+                "n" | "N" => return Ok(Answer::No),
+                "a" | "A" => return Ok(Answer::All),
+                "q" | "Q" => return Ok(Answer::Quit),
+                _ => println!(
+                    "  y - apply this change\n  n - skip this change\n  a - apply this change and all the remaining ones in the file\n  q - quit, only the changes accepted so far are written"
+                ),
+            }
+        }
+    }
+
+    /// Print how many changes were accepted in --interactive mode
+    pub fn print_interactive_summary(&self, accepted: usize, declined: usize) {
+        println!(
+            "\n{} change{} accepted, {} skipped.",
+            accepted.to_string().green().bold(),
+            if accepted > 1 { "s" } else { "" },
+            declined.to_string().red().bold()
+        );
+    }
+
     /// Warn the user when `--write` is enabled but nothing was replaced.
     pub fn warn_no_replacement_applied(&self, found_matches: usize, used_select: bool) {
-        let safety_note = "\nBe careful: this command writes changes to disk without confirmation and cannot be undone.
+        let safety_note = "\nBe careful: this command writes changes to disk without confirmation, runs can only be reverted with `fnr undo`.
 Do not use --write when looking for content to replace, either perform a dry-run or a lookup.";
 
         let message = if found_matches == 0 {
@@ -103,7 +181,8 @@ Do not use --write when looking for content to replace, either perform a dry-run
         println!("{}", message.red());
     }
 
-    /// Print the number of matches or replacements found
+    /// Print the number of matches or replacements found, and how many
+    /// replaced lines would still match the pattern
     pub fn print_matches_counts(
         &self,
         matches_count: usize,
@@ -111,6 +190,7 @@ Do not use --write when looking for content to replace, either perform a dry-run
         total_lines_walked: i32,
         select_param: &Option<Vec<usize>>,
         operation: Operation,
+        rematched_lines: usize,
     ) {
         // --- plural suffixes ---
         let matches_plural = if matches_count > 1 { "es" } else { "" };
@@ -134,7 +214,7 @@ Do not use --write when looking for content to replace, either perform a dry-run
                 if matches_count > 0 {
                     println!(
                         "\n{}",
-                        format!(
+                        format_args!(
                             "{} match{} found{}.\n{} line{} scanned.\nTip: use --write to apply.",
                             matches_count_styled,
                             matches_plural,
@@ -155,7 +235,7 @@ Do not use --write when looking for content to replace, either perform a dry-run
             Operation::Replacement => {
                 println!(
                     "\n{}",
-                    format!(
+                    format_args!(
                         "{} match{} replaced{}.\n{} line{} scanned.",
                         matches_count_styled,
                         matches_plural,
@@ -169,7 +249,7 @@ Do not use --write when looking for content to replace, either perform a dry-run
                 if matches_count > 0 {
                     println!(
                         "\n{}",
-                        format!(
+                        format_args!(
                             "{} match{} found.\n{} line{} scanned.",
                             matches_count_styled,
                             matches_plural,
@@ -182,5 +262,161 @@ Do not use --write when looking for content to replace, either perform a dry-run
                 }
             }
         }
+
+        if rematched_lines > 0 {
+            let (lines_plural, pronoun) = match rematched_lines {
+                1 => ("", "it"),
+                _ => ("s", "them"),
+            };
+            println!(
+                "{}",
+                format!(
+                    "Warning: {rematched_lines} replaced line{lines_plural} would still contain the pattern \
+                     (the new pattern contains it, or recreates it with the surrounding text), \
+                     a second run would replace {pronoun} again."
+                )
+                .yellow()
+            );
+        }
+    }
+
+    /// Tell the user how to revert the run that was just written
+    pub fn print_run_id(&self, run_id: &str) {
+        println!(
+            "Run {} recorded, use `fnr undo` to revert it.",
+            run_id.bold()
+        );
+    }
+
+    pub fn print_no_history(&self) {
+        println!("{}", "No run recorded yet.".red());
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn print_history_entry(
+        &self,
+        run_id: &str,
+        date: &str,
+        pattern: &str,
+        replacement: &str,
+        files_count: usize,
+        undone: bool,
+        complete: bool,
+    ) {
+        let files_plural = if files_count > 1 { "s" } else { "" };
+        let undone_str = if undone {
+            format!(" {}", "(undone)".bright_black())
+        } else if !complete {
+            format!(" {}", "(incomplete, see `fnr resume`)".yellow())
+        } else {
+            String::new()
+        };
+
+        println!(
+            "{}  {}  {} -> {}  {} file{}{}",
+            run_id.bold(),
+            date,
+            pattern.red(),
+            replacement.green(),
+            files_count,
+            files_plural,
+            undone_str
+        );
+    }
+
+    pub fn print_undo_summary(&self, run_id: &str, restored: usize, refused: usize) {
+        let restored_plural = if restored > 1 { "s" } else { "" };
+
+        println!(
+            "\nRun {}: {} file{} restored.",
+            run_id.bold(),
+            restored.to_string().green().bold(),
+            restored_plural
+        );
+
+        if refused > 0 {
+            println!(
+                "{}",
+                format_args!(
+                    "{refused} file(s) could not be restored, run `fnr undo {run_id}` again once solved."
+                )
+                .to_string()
+                .red()
+            );
+        }
+    }
+
+    /// Print the changes of a plan that were not applied, and why
+    /// (using stderr)
+    pub fn print_rejected_changes(&self, filename: &str, change_ids: &[&str], reason: &str) {
+        eprintln!(
+            "\nRejected {} change{} of \"{}\" ({}) => {}.",
+            change_ids.len(),
+            if change_ids.len() > 1 { "s" } else { "" },
+            filename.bold(),
+            change_ids.join(", "),
+            reason.yellow()
+        );
+    }
+
+    pub fn print_apply_summary(&self, applied: usize, rejected: usize) {
+        println!(
+            "\n{} change{} applied, {} rejected.",
+            applied.to_string().green().bold(),
+            if applied > 1 { "s" } else { "" },
+            rejected.to_string().red().bold()
+        );
+    }
+
+    pub fn print_plan_saved(&self, plan_path: &str, changes_count: usize) {
+        println!(
+            "Plan of {} change{} saved to {}, apply it with `fnr apply {}`.",
+            changes_count,
+            if changes_count > 1 { "s" } else { "" },
+            plan_path.bold(),
+            plan_path
+        );
+    }
+
+    pub fn print_hook_start(&self, command: &str) {
+        println!("\nRunning `{}`...", command.bold());
+    }
+
+    pub fn print_hook_success(&self, command: &str) {
+        println!("`{}` {}.", command, "succeeded".green());
+    }
+
+    /// Print the output of a failed --after-write command
+    /// (using stderr)
+    pub fn print_hook_output(&self, stdout: &str, stderr: &str) {
+        for output in [stdout, stderr] {
+            if !output.is_empty() {
+                eprint!("{output}");
+                if !output.ends_with('\n') {
+                    eprintln!();
+                }
+            }
+        }
+    }
+
+    /// Print the ignore files applied to the walk (using stderr)
+    pub fn print_ignore_sources(&self, sources: &[String]) {
+        eprintln!("Ignore files: {}", sources.join(", "));
+    }
+
+    /// Print that another run holds the lock of a root (using stderr)
+    pub fn print_waiting_for_lock(&self, root: &str) {
+        eprintln!(
+            "{}",
+            format!("Waiting for another fnr run writing files overlapping {root}...").yellow()
+        );
+    }
+
+    /// Print that Ctrl-C was caught (using stderr)
+    pub fn print_interrupting(&self) {
+        eprintln!(
+            "\n{}",
+            "Interrupted, stopping after the current file (Ctrl-C again to quit now)...".yellow()
+        );
     }
 }
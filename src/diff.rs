@@ -0,0 +1,138 @@
+use std::{
+    env,
+    path::{Component, Path},
+};
+
+/// Unified diff of a file where the old pattern is replaced with the new one
+/// on the given lines, as `--write` would do.
+/// `line_numbers` are 1-based, as returned by the `Searcher`.
+/// Returns an empty string if nothing changes.
+pub fn unified_diff(
+    path: &Path,
+    content: &str,
+    line_numbers: &[usize],
+    old_pattern: &str,
+    new_pattern: &str,
+    context: usize,
+) -> String {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+
+    // 0-based index and new content (ending included) of every changed line
+    let mut changes: Vec<(usize, String)> = line_numbers
+        .iter()
+        .map(|line_number| line_number - 1)
+        .filter_map(|index| {
+            let new_line = lines[index].replace(old_pattern, new_pattern);
+            (new_line != lines[index]).then_some((index, new_line))
+        })
+        .collect();
+    changes.sort_by_key(|(index, _)| *index);
+    changes.dedup_by_key(|(index, _)| *index);
+
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // Changes closer than twice the context share a hunk
+    let mut hunks: Vec<&[(usize, String)]> = Vec::new();
+    let mut hunk_start = 0;
+    for i in 1..changes.len() {
+        if changes[i].0 - changes[i - 1].0 > 2 * context + 1 {
+            hunks.push(&changes[hunk_start..i]);
+            hunk_start = i;
+        }
+    }
+    hunks.push(&changes[hunk_start..]);
+
+    let label = diff_path(path);
+    // Like git, a tab ends names containing spaces for `patch`
+    let end = if label.contains(' ') { "\t" } else { "" };
+    let mut diff = format!("--- a/{label}{end}\n+++ b/{label}{end}\n");
+    // Lines added minus lines removed by the previous hunks
+    let mut offset: isize = 0;
+
+    for hunk in hunks {
+        let old_start = hunk[0].0.saturating_sub(context);
+        let old_end = (hunk[hunk.len() - 1].0 + context + 1).min(lines.len());
+
+        let mut body = String::new();
+        let mut old_count = 0;
+        let mut new_count = 0;
+        let mut hunk_changes = hunk.iter().peekable();
+        let mut index = old_start;
+
+        while index < old_end {
+            // A block of consecutive changed lines: removals first, then additions
+            let mut added = String::new();
+            while let Some((_, new_line)) = hunk_changes.next_if(|(i, _)| *i == index) {
+                push_line(&mut body, '-', lines[index]);
+                old_count += 1;
+                for line in new_line.split_inclusive('\n') {
+                    push_line(&mut added, '+', line);
+                    new_count += 1;
+                }
+                index += 1;
+            }
+
+            if !added.is_empty() {
+                body.push_str(&added);
+                continue;
+            }
+
+            push_line(&mut body, ' ', lines[index]);
+            old_count += 1;
+            new_count += 1;
+            index += 1;
+        }
+
+        let new_start = old_start.saturating_add_signed(offset);
+        diff.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            range(old_start, old_count),
+            range(new_start, new_count)
+        ));
+        diff.push_str(&body);
+
+        offset += new_count as isize - old_count as isize;
+    }
+
+    diff
+}
+
+fn push_line(body: &mut String, prefix: char, line: &str) {
+    body.push(prefix);
+    body.push_str(line);
+    if !line.ends_with('\n') {
+        body.push_str("\n\\ No newline at end of file\n");
+    }
+}
+
+/// `start,count` part of a hunk header, `start` being 0-based
+fn range(start: usize, count: usize) -> String {
+    match count {
+        // An empty range starts at the line before it
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, count),
+    }
+}
+
+/// Path of a file as written in diff headers: relative to the current
+/// directory when possible, with `/` separators, so `patch -p1` and
+/// `git apply` find the file once the `a/` and `b/` prefixes are stripped.
+fn diff_path(path: &Path) -> String {
+    let relative = env::current_dir()
+        .ok()
+        .and_then(|current_dir| path.strip_prefix(current_dir).ok())
+        .unwrap_or(path);
+
+    relative
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            Component::ParentDir => Some("..".into()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
@@ -0,0 +1,175 @@
+use anyhow::{Context, Result};
+use globset::GlobBuilder;
+use std::{
+    fs,
+    path::{self, Path},
+};
+
+/// Line endings and final newline of a file, as set by `.editorconfig` files
+/// https://editorconfig.org
+#[derive(Debug, Default, PartialEq)]
+pub struct Style {
+    end_of_line: Option<&'static str>,
+    insert_final_newline: Option<bool>,
+}
+
+/// Sections of a single `.editorconfig` file: glob and properties
+struct EditorConfig {
+    root: bool,
+    sections: Vec<(String, Vec<(String, String)>)>,
+}
+
+impl EditorConfig {
+    fn parse(content: &str) -> Self {
+        let mut root = false;
+        let mut sections: Vec<(String, Vec<(String, String)>)> = Vec::new();
+
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if let Some(glob) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                sections.push((glob.to_string(), Vec::new()));
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let key = key.trim().to_lowercase();
+            let value = value.trim().to_lowercase();
+
+            match sections.last_mut() {
+                Some((_, properties)) => properties.push((key, value)),
+                // Preamble, before the first section
+                None => root |= key == "root" && value == "true",
+            }
+        }
+
+        Self { root, sections }
+    }
+}
+
+impl Style {
+    /// Style of a file, from the `.editorconfig` files of its directory and
+    /// its parents, up to the one declaring `root = true`.
+    pub fn for_file(file_path: &Path) -> Result<Self> {
+        let file_path = path::absolute(file_path)?;
+
+        // Nearest first
+        let mut configs = Vec::new();
+        for dir in file_path.ancestors().skip(1) {
+            let config_path = dir.join(".editorconfig");
+            let Ok(content) = fs::read_to_string(&config_path) else {
+                continue;
+            };
+
+            let config = EditorConfig::parse(&content);
+            let root = config.root;
+            configs.push((dir.to_path_buf(), config));
+            if root {
+                break;
+            }
+        }
+
+        // Properties of the nearest files, and of later sections, win
+        let mut style = Self::default();
+        for (dir, config) in configs.iter().rev() {
+            let Ok(relative_path) = file_path.strip_prefix(dir) else {
+                continue;
+            };
+            let relative_path = relative_path.to_string_lossy().replace('\\', "/");
+
+            for (glob, properties) in &config.sections {
+                if !section_matches(glob, &relative_path)? {
+                    continue;
+                }
+
+                for (key, value) in properties {
+                    match (key.as_str(), value.as_str()) {
+                        ("end_of_line", "lf") => style.end_of_line = Some("\n"),
+                        ("end_of_line", "crlf") => style.end_of_line = Some("\r\n"),
+                        ("end_of_line", "cr") => style.end_of_line = Some("\r"),
+                        ("insert_final_newline", "true") => style.insert_final_newline = Some(true),
+                        ("insert_final_newline", "false") => {
+                            style.insert_final_newline = Some(false)
+                        }
+                        // `unset` goes back to leaving the file as is
+                        ("end_of_line", "unset") => style.end_of_line = None,
+                        ("insert_final_newline", "unset") => style.insert_final_newline = None,
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        Ok(style)
+    }
+
+    /// Fix the line endings and the final newline of a content
+    pub fn apply(&self, content: &str) -> String {
+        let mut fixed = match self.end_of_line {
+            Some(end_of_line) => normalize_line_endings(content, end_of_line),
+            None => content.to_string(),
+        };
+
+        match self.insert_final_newline {
+            Some(true) if !fixed.is_empty() && !fixed.ends_with(['\n', '\r']) => {
+                let end_of_line = self
+                    .end_of_line
+                    .unwrap_or_else(|| first_line_ending(content));
+                fixed.push_str(end_of_line);
+            }
+            Some(false) => fixed.truncate(fixed.trim_end_matches(['\n', '\r']).len()),
+            _ => {}
+        }
+
+        fixed
+    }
+}
+
+/// Like in .gitignore files, a glob without `/` matches file names
+/// at any depth, other globs match paths relative to the `.editorconfig`.
+fn section_matches(glob: &str, relative_path: &str) -> Result<bool> {
+    let glob = match glob.contains('/') {
+        true => glob.trim_start_matches('/').to_string(),
+        false => format!("**/{glob}"),
+    };
+
+    let matcher = GlobBuilder::new(&glob)
+        .literal_separator(true)
+        .build()
+        .with_context(|| format!("Invalid section [{glob}] in .editorconfig"))?
+        .compile_matcher();
+
+    Ok(matcher.is_match(relative_path))
+}
+
+fn normalize_line_endings(content: &str, end_of_line: &str) -> String {
+    let mut normalized = String::with_capacity(content.len());
+    let mut chars = content.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' => {
+                chars.next_if_eq(&'\n');
+                normalized.push_str(end_of_line);
+            }
+            '\n' => normalized.push_str(end_of_line),
+            c => normalized.push(c),
+        }
+    }
+
+    normalized
+}
+
+/// Line ending already used by a content, "\n" if it has a single line
+fn first_line_ending(content: &str) -> &'static str {
+    match content.find(['\n', '\r']) {
+        Some(index) if content[index..].starts_with("\r\n") => "\r\n",
+        Some(index) if content[index..].starts_with('\r') => "\r",
+        _ => "\n",
+    }
+}
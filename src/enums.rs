@@ -1,7 +1,41 @@
-
-
 pub enum Operation {
     Match,
     Replacement,
     Lookup,
 }
+
+/// Result of writing a file to disk
+pub enum WriteOutcome {
+    Written,
+    /// The new content is ready, written when the transaction is committed
+    Staged,
+    /// The file was left untouched, for the given reason
+    Skipped(String),
+    /// The file was left untouched on purpose, for the given reason:
+    /// unlike `Skipped`, this does not abort a --transactional run.
+    /// Used for links only followed to be searched, or already written
+    /// through another path.
+    Ignored(String),
+}
+
+/// Answer to a --interactive confirmation
+pub enum Answer {
+    Yes,
+    No,
+    /// Accept this change and the remaining ones of the file
+    All,
+    /// Stop here, only write the changes accepted so far
+    Quit,
+}
+
+/// What to do with symbolic links found while walking, see --symlinks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum SymlinkPolicy {
+    /// Do not search symbolic links
+    #[default]
+    Skip,
+    /// Search the targets of symbolic links, but never write them
+    FollowRead,
+    /// Search and write the targets of symbolic links, if they are inside the search root
+    FollowWrite,
+}
@@ -0,0 +1,63 @@
+use anyhow::{Context, Result};
+use std::process::{Command, ExitStatus};
+
+use crate::{journal, Console};
+
+/// `--after-write CMD`: run a command once the changes are written.
+/// If it fails, its output is shown and with --revert-on-failure,
+/// every file modified by the run is restored.
+pub fn after_write(
+    command: &str,
+    run_id: Option<&str>,
+    revert_on_failure: bool,
+    console: &Console,
+) -> Result<()> {
+    console.print_hook_start(command);
+
+    let output = shell(command)
+        .output()
+        .with_context(|| format!("Could not run `{command}`"))?;
+
+    if output.status.success() {
+        console.print_hook_success(command);
+        return Ok(());
+    }
+
+    console.print_hook_output(
+        &String::from_utf8_lossy(&output.stdout),
+        &String::from_utf8_lossy(&output.stderr),
+    );
+    let failure = format!("`{command}` failed with {}", describe(output.status));
+
+    match (run_id, revert_on_failure) {
+        (Some(run_id), true) => match journal::revert(run_id, console)? {
+            0 => anyhow::bail!("{failure}, every modified file was restored."),
+            refused => anyhow::bail!("{failure}, {refused} file(s) could not be restored."),
+        },
+        (Some(run_id), false) => {
+            anyhow::bail!("{failure}, the changes were kept. Revert them with `fnr undo {run_id}`.")
+        }
+        (None, _) => anyhow::bail!("{failure}."),
+    }
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(not(unix))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+fn describe(status: ExitStatus) -> String {
+    match status.code() {
+        Some(code) => format!("exit code {code}"),
+        None => "no exit code, killed by a signal".to_string(),
+    }
+}
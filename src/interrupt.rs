@@ -0,0 +1,95 @@
+use std::{
+    collections::BTreeSet,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, MutexGuard, PoisonError,
+    },
+};
+use tempfile::NamedTempFile;
+
+use crate::Console;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Temporary files created in the searched tree and not renamed over
+/// their file yet, removed if a second Ctrl-C exits the run.
+/// Held while a file is overwritten in place, so the exit waits for it.
+static TEMP_FILES: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+/// Exit code of a run stopped by Ctrl-C, like shells report it
+pub const INTERRUPTED_EXIT_CODE: u8 = 130;
+
+/// Error returned when a --write run stops because of Ctrl-C
+#[derive(Debug)]
+pub struct Interrupted {
+    /// Run recording the files written before the interruption, if any
+    pub run_id: Option<String>,
+}
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.run_id {
+            Some(run_id) => write!(
+                f,
+                "Interrupted. The files written so far are recorded in run {run_id}, \
+                 finish it with `fnr resume` or revert it with `fnr undo`."
+            ),
+            None => write!(f, "Interrupted, no file was modified."),
+        }
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+/// Catch Ctrl-C, so a file being written is finished and recorded
+/// in the journal before the run stops. A second Ctrl-C exits at once:
+/// files replaced through a temporary file are fully written or left
+/// untouched and the temporary files are removed, but a file being
+/// overwritten in place (see `uninterruptible()`) is finished first.
+/// The journal may then miss the file being written.
+pub fn install_handler() {
+    // Fails if a handler is already installed, which is fine
+    let _ = ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            // Kept locked until the process exits
+            let temp_files = temp_files();
+            for path in temp_files.iter() {
+                let _ = fs::remove_file(path);
+            }
+            process::exit(INTERRUPTED_EXIT_CODE.into());
+        }
+        Console::new().print_interrupting();
+    });
+}
+
+fn temp_files() -> MutexGuard<'static, BTreeSet<PathBuf>> {
+    TEMP_FILES.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Create a temporary file in `directory`, removed if a second Ctrl-C
+/// exits the run before `untrack_temp_file()` is called
+pub fn temp_file_in(directory: &Path) -> io::Result<NamedTempFile> {
+    let mut temp_files = temp_files();
+    let temp_file = NamedTempFile::new_in(directory)?;
+    temp_files.insert(temp_file.path().to_path_buf());
+    Ok(temp_file)
+}
+
+/// The temporary file was renamed over its file, or removed
+pub fn untrack_temp_file(path: &Path) {
+    temp_files().remove(path);
+}
+
+/// Run `f` without being stopped by a second Ctrl-C, which waits for it
+pub fn uninterruptible<T>(f: impl FnOnce() -> T) -> T {
+    let _temp_files = temp_files();
+    f()
+}
+
+/// Whether Ctrl-C was pressed since the handler was installed
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
@@ -0,0 +1,483 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    enums::WriteOutcome, lock::RootLock, replacer::write_atomically_with, Console, Settings,
+};
+
+/// Information about a --write run, stored in `run.json`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunInfo {
+    pub id: String,
+    /// Seconds since the unix epoch
+    pub timestamp: u64,
+    pub pattern: String,
+    pub replacement: String,
+    pub root: PathBuf,
+    /// Command line of the run, program name excluded, to resume it
+    #[serde(default)]
+    pub arguments: Vec<String>,
+    #[serde(default)]
+    pub working_directory: Option<PathBuf>,
+}
+
+/// A file modified during a run, stored as one line of `files.jsonl`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Absolute path of the modified file
+    pub path: PathBuf,
+    pub hash_before: String,
+    pub hash_after: String,
+    /// Name of the file holding the original content, inside the run directory
+    pub original: String,
+}
+
+/// A past run read back from the state directory
+pub struct Run {
+    pub info: RunInfo,
+    pub entries: Vec<JournalEntry>,
+    pub undone: bool,
+    /// False if the run was interrupted, or failed, before its end
+    pub complete: bool,
+    dir: PathBuf,
+}
+
+/// Journal of the current --write run.
+/// Every modified file is recorded along with its original content,
+/// so the run can be reverted with `fnr undo`.
+pub struct Journal {
+    dir: PathBuf,
+    id: String,
+    saved_originals: usize,
+}
+
+impl Journal {
+    pub fn create(pattern: &str, replacement: &str, root: &Path) -> Result<Self> {
+        let runs_dir = runs_dir()?;
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+        // Run ids sort chronologically, the pid avoids collisions
+        // between runs started in the same second.
+        let id = format!("{}-{}", compact_timestamp(timestamp), process::id());
+        let dir = runs_dir.join(&id);
+        fs::create_dir_all(dir.join("files"))
+            .with_context(|| format!("Could not create journal in {}", dir.display()))?;
+
+        let info = RunInfo {
+            id: id.clone(),
+            timestamp,
+            pattern: pattern.to_string(),
+            replacement: replacement.to_string(),
+            root: fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf()),
+            arguments: env::args_os()
+                .skip(1)
+                .map(|argument| argument.to_string_lossy().into_owned())
+                .collect(),
+            working_directory: env::current_dir().ok(),
+        };
+        // Removed by `finish()`, so runs that did not end can be resumed
+        fs::write(dir.join(INCOMPLETE), "")?;
+        fs::write(dir.join("run.json"), serde_json::to_string_pretty(&info)?)?;
+
+        Ok(Self {
+            dir,
+            id,
+            saved_originals: 0,
+        })
+    }
+
+    /// Journal of an interrupted run, to record the files written
+    /// when resuming it
+    pub fn reopen(run: &Run) -> Result<Self> {
+        let saved_originals = fs::read_dir(run.dir.join("files"))?.count();
+
+        Ok(Self {
+            dir: run.dir.clone(),
+            id: run.info.id.clone(),
+            saved_originals,
+        })
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Mark the run as complete, it can no longer be resumed
+    pub fn finish(&self) -> Result<()> {
+        match fs::remove_file(self.dir.join(INCOMPLETE)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Mark the run as undone, when its files were restored
+    /// by --transactional
+    pub fn mark_undone(&self) -> Result<()> {
+        fs::write(self.dir.join("undone"), "")?;
+        Ok(())
+    }
+
+    /// Store the content of a file before it is modified,
+    /// returns the name to reference it from a `JournalEntry`.
+    pub fn save_original(&mut self, content: &[u8]) -> Result<String> {
+        let name = format!("files/{}", self.saved_originals);
+        fs::write(self.dir.join(&name), content)
+            .with_context(|| format!("Could not write journal in {}", self.dir.display()))?;
+        self.saved_originals += 1;
+
+        Ok(name)
+    }
+
+    /// Like `save_original()`, copying a file too large to be read in memory
+    pub fn save_original_file(&mut self, file_path: &Path) -> Result<String> {
+        let name = format!("files/{}", self.saved_originals);
+        fs::copy(file_path, self.dir.join(&name))
+            .with_context(|| format!("Could not write journal in {}", self.dir.display()))?;
+        self.saved_originals += 1;
+
+        Ok(name)
+    }
+
+    /// Where the original content saved under `name` is stored
+    pub fn original_path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    /// Append a modified file to the journal
+    pub fn record(&mut self, entry: &JournalEntry) -> Result<()> {
+        let mut files = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join("files.jsonl"))?;
+        writeln!(files, "{}", serde_json::to_string(entry)?)?;
+
+        Ok(())
+    }
+}
+
+impl Run {
+    fn load(dir: PathBuf) -> Result<Self> {
+        let info: RunInfo = serde_json::from_str(&fs::read_to_string(dir.join("run.json"))?)
+            .with_context(|| format!("Invalid journal in {}", dir.display()))?;
+
+        let entries = match fs::read_to_string(dir.join("files.jsonl")) {
+            Ok(content) => content
+                .lines()
+                .map(serde_json::from_str)
+                .collect::<Result<Vec<JournalEntry>, _>>()
+                .with_context(|| format!("Invalid journal in {}", dir.display()))?,
+            Err(_) => Vec::new(),
+        };
+
+        Ok(Self {
+            info,
+            entries,
+            undone: dir.join("undone").exists(),
+            complete: !dir.join(INCOMPLETE).exists(),
+            dir,
+        })
+    }
+
+    /// Where the original content of a file modified by the run is saved,
+    /// `path` being canonical
+    pub fn original_path(&self, path: &Path) -> Option<PathBuf> {
+        self.entries
+            .iter()
+            .find(|entry| entry.path == path)
+            .map(|entry| self.dir.join(&entry.original))
+    }
+
+    pub fn original_file(&self, entry: &JournalEntry) -> Result<File> {
+        File::open(self.dir.join(&entry.original))
+            .with_context(|| format!("Missing original content of {}", entry.path.display()))
+    }
+
+    fn mark_undone(&self) -> Result<()> {
+        fs::write(self.dir.join("undone"), "")?;
+        Ok(())
+    }
+}
+
+/// Directory where fnr keeps its state.
+/// Can be overridden with the FNR_STATE_DIR environment variable.
+pub fn state_dir() -> Result<PathBuf> {
+    if let Some(dir) = env::var_os("FNR_STATE_DIR") {
+        return Ok(PathBuf::from(dir));
+    }
+    if let Some(dir) = env::var_os("XDG_STATE_HOME") {
+        return Ok(PathBuf::from(dir).join("fnr"));
+    }
+    if let Some(dir) = env::var_os("LOCALAPPDATA") {
+        return Ok(PathBuf::from(dir).join("fnr"));
+    }
+    if let Some(home) = env::var_os("HOME") {
+        return Ok(PathBuf::from(home).join(".local/state/fnr"));
+    }
+
+    anyhow::bail!("Could not find a state directory, set FNR_STATE_DIR")
+}
+
+/// Marker file of the runs that did not end
+const INCOMPLETE: &str = "incomplete";
+
+fn runs_dir() -> Result<PathBuf> {
+    Ok(state_dir()?.join("runs"))
+}
+
+/// Past runs that modified at least one file, oldest first
+pub fn list_runs() -> Result<Vec<Run>> {
+    let runs_dir = runs_dir()?;
+    if !runs_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut dirs: Vec<PathBuf> = fs::read_dir(&runs_dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.join("run.json").exists())
+        .collect();
+    dirs.sort();
+
+    let mut runs = Vec::new();
+    for dir in dirs {
+        let run = Run::load(dir)?;
+        if !run.entries.is_empty() {
+            runs.push(run);
+        }
+    }
+
+    Ok(runs)
+}
+
+/// SHA-256 of a content, as an hex string
+pub fn hash(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// SHA-256 of a file, read through a buffer
+pub fn hash_file(file_path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(file_path)?, &mut hasher)
+        .with_context(|| format!("Could not read {}", file_path.display()))?;
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect())
+}
+
+/// `fnr history`: list past runs, most recent first
+pub fn history(console: &Console) -> Result<()> {
+    let runs = list_runs()?;
+    if runs.is_empty() {
+        console.print_no_history();
+        return Ok(());
+    }
+
+    for run in runs.iter().rev() {
+        console.print_history_entry(
+            &run.info.id,
+            &format_timestamp(run.info.timestamp),
+            &run.info.pattern,
+            &run.info.replacement,
+            run.entries.len(),
+            run.undone,
+            run.complete,
+        );
+    }
+
+    Ok(())
+}
+
+/// `fnr undo [RUN_ID]`: restore the files modified by a run,
+/// the latest one not undone yet by default.
+/// A file is only restored if it was not modified since the run.
+pub fn undo(run_id: Option<String>, console: &Console) -> Result<()> {
+    let runs = list_runs()?;
+
+    let run = match &run_id {
+        Some(run_id) => runs
+            .into_iter()
+            .find(|run| &run.info.id == run_id)
+            .with_context(|| format!("No run with id {run_id}, see `fnr history`"))?,
+        None => runs
+            .into_iter()
+            .rev()
+            .find(|run| !run.undone)
+            .context("No run to undo")?,
+    };
+
+    if run.undone {
+        anyhow::bail!("Run {} was already undone", run.info.id);
+    }
+
+    let _lock = RootLock::acquire(&run.info.root, None, console)?;
+    restore(&run, false, console)?;
+
+    Ok(())
+}
+
+/// Run to finish with `fnr resume [RUN_ID]`,
+/// the latest interrupted one by default
+pub fn resumable_run(run_id: Option<String>) -> Result<Run> {
+    let runs = list_runs()?;
+
+    let run = match &run_id {
+        Some(run_id) => runs
+            .into_iter()
+            .find(|run| &run.info.id == run_id)
+            .with_context(|| format!("No run with id {run_id}, see `fnr history`"))?,
+        None => runs
+            .into_iter()
+            .rev()
+            .find(|run| !run.complete && !run.undone)
+            .context("No interrupted run to resume")?,
+    };
+
+    if run.undone {
+        anyhow::bail!("Run {} was undone", run.info.id);
+    }
+    if run.complete {
+        anyhow::bail!(
+            "Run {} is complete, there is nothing to resume",
+            run.info.id
+        );
+    }
+    if run.info.arguments.is_empty() || run.info.working_directory.is_none() {
+        anyhow::bail!(
+            "Run {} was recorded by an older version of fnr and cannot be resumed",
+            run.info.id
+        );
+    }
+
+    Ok(run)
+}
+
+/// `--revert-on-failure`: restore the files modified by the current run,
+/// including the ones the --after-write command modified since.
+/// Returns the number of files that could not be restored.
+pub fn revert(run_id: &str, console: &Console) -> Result<usize> {
+    let run = list_runs()?
+        .into_iter()
+        .find(|run| run.info.id == run_id)
+        .with_context(|| format!("No run with id {run_id}, see `fnr history`"))?;
+
+    restore(&run, true, console)
+}
+
+/// Restore the files modified by a run, returns the number of files refused.
+/// Unless `overwrite` is true, a file modified since the run is refused.
+fn restore(run: &Run, overwrite: bool, console: &Console) -> Result<usize> {
+    // Restoring is forced: a read-only file we wrote must be restored too.
+    let settings = Settings {
+        force: true,
+        ..Settings::default()
+    };
+
+    let mut restored = 0;
+    let mut refused = 0;
+
+    for entry in run.entries.iter().rev() {
+        let filename = entry.path.to_string_lossy();
+
+        let current_hash = match hash_file(&entry.path) {
+            Ok(current_hash) => current_hash,
+            Err(e) => {
+                console.print_skipped_file(&filename, &e.to_string());
+                refused += 1;
+                continue;
+            }
+        };
+
+        // Already restored, by a previous partial undo
+        if current_hash == entry.hash_before {
+            restored += 1;
+            continue;
+        }
+
+        if current_hash != entry.hash_after && !overwrite {
+            console.print_skipped_file(&filename, "file was modified after the run");
+            refused += 1;
+            continue;
+        }
+
+        // Streamed, like the files written by the run
+        let mut original = run.original_file(entry)?;
+        let write_content = |writer: &mut dyn Write| {
+            io::copy(&mut original, writer)?;
+            Ok(())
+        };
+        match write_atomically_with(&entry.path, write_content, None, &settings)? {
+            WriteOutcome::Written | WriteOutcome::Staged => restored += 1,
+            WriteOutcome::Skipped(reason) | WriteOutcome::Ignored(reason) => {
+                console.print_skipped_file(&filename, &reason);
+                refused += 1;
+            }
+        }
+    }
+
+    // A partially undone run can be undone again once conflicts are solved
+    if refused == 0 {
+        run.mark_undone()?;
+    }
+
+    console.print_undo_summary(&run.info.id, restored, refused);
+
+    Ok(refused)
+}
+
+/// Convert a unix timestamp to a (year, month, day, hour, minute, second) UTC date.
+/// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_timestamp(timestamp: u64) -> (i64, u64, u64, u64, u64, u64) {
+    let days = (timestamp / 86_400) as i64;
+    let seconds = timestamp % 86_400;
+
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097) as u64;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe as i64 + era * 400 + i64::from(month <= 2);
+
+    (
+        year,
+        month,
+        day,
+        seconds / 3_600,
+        seconds % 3_600 / 60,
+        seconds % 60,
+    )
+}
+
+/// `2026-10-19 01:43:47 UTC`
+pub fn format_timestamp(timestamp: u64) -> String {
+    let (year, month, day, hour, minute, second) = civil_from_timestamp(timestamp);
+    format!("{year:04}-{month:02}-{day:02} {hour:02}:{minute:02}:{second:02} UTC")
+}
+
+/// `2026-10-19T01:43:47Z`
+pub fn rfc3339_timestamp(timestamp: u64) -> String {
+    let (year, month, day, hour, minute, second) = civil_from_timestamp(timestamp);
+    format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}Z")
+}
+
+/// `20261019-014347`
+fn compact_timestamp(timestamp: u64) -> String {
+    let (year, month, day, hour, minute, second) = civil_from_timestamp(timestamp);
+    format!("{year:04}{month:02}{day:02}-{hour:02}{minute:02}{second:02}")
+}
@@ -1,17 +1,31 @@
 mod app;
+mod audit;
+mod config;
 mod console;
+mod diff;
+mod editorconfig;
 mod enums;
+mod hook;
+mod interrupt;
+mod journal;
+mod lock;
 mod parsing;
 mod pattern_matcher;
+mod plan;
+mod protected;
 mod replacer;
+mod review;
 mod settings;
 mod walker;
 
 pub use app::run;
 pub use console::Console;
-pub use enums::Operation;
+pub use enums::{Answer, Operation, SymlinkPolicy, WriteOutcome};
+pub use interrupt::{Interrupted, INTERRUPTED_EXIT_CODE};
 pub use parsing::parse_select;
-pub use pattern_matcher::Searcher;
-pub use replacer::Replacer;
+pub use pattern_matcher::{Fingerprint, SearchResult, Searcher};
+pub use plan::{Plan, PlannedChange};
+pub use protected::ProtectedPaths;
+pub use replacer::{Edit, Replacer};
 pub use settings::Settings;
 pub use walker::Walker;
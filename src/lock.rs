@@ -0,0 +1,96 @@
+use anyhow::{Context, Result};
+use std::{
+    fs::{self, File, OpenOptions, TryLockError},
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    interrupt::{self, Interrupted},
+    journal, Console,
+};
+
+/// Delay between two attempts to take a lock
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Advisory lock held by a run writing files under a root, released when dropped.
+///
+/// The root is locked exclusively and each of its ancestors is locked shared,
+/// so runs on the same or nested trees wait for each other, while runs on
+/// separate trees do not. Locks are taken from `/` down, in the same order
+/// for every run.
+pub struct RootLock {
+    _files: Vec<File>,
+}
+
+impl RootLock {
+    /// Lock `root`, waiting for other runs at most `timeout` seconds
+    /// (forever if None).
+    pub fn acquire(root: &Path, timeout: Option<u64>, console: &Console) -> Result<Self> {
+        let root = fs::canonicalize(root)
+            .with_context(|| format!("Could not resolve {}", root.display()))?;
+        let locks_dir = journal::state_dir()?.join("locks");
+        fs::create_dir_all(&locks_dir)
+            .with_context(|| format!("Could not create {}", locks_dir.display()))?;
+
+        let deadline = timeout.map(|timeout| Instant::now() + Duration::from_secs(timeout));
+        let mut waiting = false;
+        let mut files = Vec::new();
+
+        let mut paths: Vec<&Path> = root.ancestors().collect();
+        paths.reverse();
+
+        for path in paths {
+            let exclusive = path == root;
+            let lock_path = locks_dir.join(format!(
+                "{}.lock",
+                &journal::hash(path.as_os_str().as_encoded_bytes())[..16]
+            ));
+            let file = OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(&lock_path)
+                .with_context(|| format!("Could not open lock {}", lock_path.display()))?;
+
+            loop {
+                let locked = match exclusive {
+                    true => file.try_lock(),
+                    false => file.try_lock_shared(),
+                };
+
+                match locked {
+                    Ok(()) => break,
+                    Err(TryLockError::Error(e)) => {
+                        return Err(e)
+                            .with_context(|| format!("Could not lock {}", lock_path.display()));
+                    }
+                    Err(TryLockError::WouldBlock) => {}
+                }
+
+                if !waiting {
+                    console.print_waiting_for_lock(&root.to_string_lossy());
+                    waiting = true;
+                }
+
+                if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    anyhow::bail!(
+                        "Another fnr run is writing files overlapping {}, \
+                         gave up waiting for it (see --lock-timeout).",
+                        root.display()
+                    );
+                }
+                // Polled rather than blocking, so Ctrl-C stops waiting
+                if interrupt::interrupted() {
+                    return Err(Interrupted { run_id: None }.into());
+                }
+                thread::sleep(RETRY_DELAY);
+            }
+
+            files.push(file);
+        }
+
+        Ok(Self { _files: files })
+    }
+}
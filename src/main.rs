@@ -1,5 +1,16 @@
-use anyhow::Result;
+use std::process::ExitCode;
 
-fn main() -> Result<()> {
-    fnr::run()
+fn main() -> ExitCode {
+    match fnr::run() {
+        Ok(exit_code) => exit_code,
+        Err(e) if e.downcast_ref::<fnr::Interrupted>().is_some() => {
+            eprintln!("{e}");
+            ExitCode::from(fnr::INTERRUPTED_EXIT_CODE)
+        }
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            // 1 is reserved for --check finding replacements to make
+            ExitCode::from(2)
+        }
+    }
 }
@@ -1,13 +1,91 @@
 use std::{
-    fs::File,
-    io::{BufRead, BufReader},
-    path,
+    fs::{self, File, Metadata},
+    io::{BufRead, BufReader, Read},
+    path, str,
+    time::SystemTime,
 };
 
 use anyhow::{Context, Result};
 
 use crate::{Console, Settings};
 
+/// Size and modification time of a file when it was searched,
+/// used to detect changes made by another program before writing it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fingerprint {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl Fingerprint {
+    pub fn new(metadata: &Metadata) -> Self {
+        Self {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        }
+    }
+
+    /// Returns true if the file on disk still matches this fingerprint
+    pub fn matches(&self, path: &path::Path) -> bool {
+        fs::metadata(path).is_ok_and(|metadata| &Fingerprint::new(&metadata) == self)
+    }
+}
+
+/// UTF-8 byte order mark, kept as is at the start of files
+pub(crate) const BOM: &str = "\u{FEFF}";
+
+/// Size of the buffer files larger than --stream-above are read through
+pub(crate) const STREAM_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Split the line ending ("\n" or "\r\n") off a line read with `read_until()`
+pub(crate) fn split_line_ending(line: &str) -> (&str, &str) {
+    let text = line
+        .strip_suffix('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .unwrap_or(line);
+
+    line.split_at(text.len())
+}
+
+/// Byte range of every line of a content, line endings ("\n" or "\r\n") excluded.
+/// Lines are the same as the ones returned by `str::lines()`, except that
+/// a leading byte order mark is not part of the first line, so edits never touch it.
+pub fn line_spans(content: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let bom_len = if content.starts_with(BOM) {
+        BOM.len()
+    } else {
+        0
+    };
+    let mut start = bom_len;
+
+    for line in content[bom_len..].split_inclusive('\n') {
+        let (text, _) = split_line_ending(line);
+        spans.push((start, start + text.len()));
+        start += line.len();
+    }
+
+    spans
+}
+
+/// Outcome of searching a single file
+pub struct SearchResult {
+    /// Full content of the file, kept so the replacer
+    /// does not have to read the file a second time.
+    /// Empty for streamed files.
+    pub content: String,
+    /// If true, the file was larger than --stream-above and read line
+    /// by line, the replacer streams it again to write it.
+    pub streamed: bool,
+    /// State of the file when its content was read
+    pub fingerprint: Option<Fingerprint>,
+    /// Line numbers (1-based) and content of the lines that matched
+    pub matches: Vec<(usize, String)>,
+    /// Number of lines scanned in the file
+    pub walked_lines: i32,
+}
+
+#[derive(Default)]
 pub struct Searcher {}
 
 impl Searcher {
@@ -16,7 +94,7 @@ impl Searcher {
     }
 
     /// Method to search for a pattern in a specific file
-    /// It builds and returns a list of matches
+    /// It builds and returns a list of matches along with the file content
     /// If verbose is true, we print errors to the console
     pub fn lookup(
         &self,
@@ -24,47 +102,137 @@ impl Searcher {
         pattern: &str,
         settings: &Settings,
         console: &Console,
-    ) -> Result<(Vec<(usize, String)>, i32)> {
-        let file =
+    ) -> Result<SearchResult> {
+        let mut file =
             File::open(path).with_context(|| format!("Could not open {}", path.display()))?;
-        let reader = BufReader::new(file);
+        let metadata = file.metadata()?;
+        // Taken before reading, so a change made while reading is detected as well
+        let fingerprint = Fingerprint::new(&metadata);
+
+        if settings
+            .stream_above
+            .is_some_and(|stream_above| metadata.len() > stream_above)
+        {
+            return self.lookup_streamed(file, path, fingerprint, pattern, settings, console);
+        }
+
+        let mut content = String::new();
+        match file.read_to_string(&mut content) {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                return not_utf8(path, &e.to_string(), settings, console);
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("Could not read {}", path.display()));
+            }
+        };
+
+        let mut matches = Vec::new();
+        let mut walked_lines = 0;
+
+        for (index, (start, end)) in line_spans(&content).into_iter().enumerate() {
+            walked_lines += 1;
+            if let Some(line) = match_line(&content[start..end], pattern, settings) {
+                matches.push((index + 1, line));
+            }
+        }
 
+        Ok(SearchResult {
+            content,
+            streamed: false,
+            fingerprint: Some(fingerprint),
+            matches,
+            walked_lines,
+        })
+    }
+
+    /// Search a file larger than --stream-above line by line,
+    /// through a bounded buffer, without keeping its content.
+    fn lookup_streamed(
+        &self,
+        file: File,
+        path: &path::Path,
+        fingerprint: Fingerprint,
+        pattern: &str,
+        settings: &Settings,
+        console: &Console,
+    ) -> Result<SearchResult> {
+        let mut reader = BufReader::with_capacity(STREAM_BUFFER_SIZE, file);
+        let mut buffer = Vec::new();
         let mut matches = Vec::new();
         let mut walked_lines = 0;
 
-        for (index, line) in reader.lines().enumerate() {
-            let line = match line {
-                Ok(line) => {
-                    if settings.ignore_case {
-                        line.to_lowercase()
-                    } else {
-                        line
-                    }
-                }
-
-                Err(e) => {
-                    let path_str = match path.to_str() {
-                        Some(path_str) => path_str,
-                        None => {
-                            return Err(anyhow::anyhow!("Could not convert path to string."));
-                        }
-                    };
-
-                    if settings.verbose {
-                        console.print_error(e.to_string().as_str(), &path_str);
-                    }
-
-                    // If the file is not utf-8 encoded, we early return an empty vector
-                    return Ok((Vec::new(), walked_lines));
-                }
+        loop {
+            buffer.clear();
+            let read = reader
+                .read_until(b'\n', &mut buffer)
+                .with_context(|| format!("Could not read {}", path.display()))?;
+            if read == 0 {
+                break;
+            }
+
+            // A multi-byte character never spans a line ending,
+            // so checking every line checks the whole file.
+            let line = match str::from_utf8(&buffer) {
+                Ok(line) => line,
+                Err(e) => return not_utf8(path, &e.to_string(), settings, console),
             };
+            let line = match walked_lines {
+                0 => line.strip_prefix(BOM).unwrap_or(line),
+                _ => line,
+            };
+            let (text, _) = split_line_ending(line);
 
             walked_lines += 1;
-            if line.contains(&pattern) {
-                matches.push((index + 1, line));
+            if let Some(line) = match_line(text, pattern, settings) {
+                matches.push((walked_lines as usize, line));
             }
         }
 
-        Ok((matches, walked_lines))
+        Ok(SearchResult {
+            content: String::new(),
+            streamed: true,
+            fingerprint: Some(fingerprint),
+            matches,
+            walked_lines,
+        })
     }
 }
+
+/// The line as compared to the pattern, if it matches
+fn match_line(line: &str, pattern: &str, settings: &Settings) -> Option<String> {
+    let line = if settings.ignore_case {
+        line.to_lowercase()
+    } else {
+        line.to_string()
+    };
+
+    line.contains(pattern).then_some(line)
+}
+
+/// If the file is not utf-8 encoded, we early return an empty result
+fn not_utf8(
+    path: &path::Path,
+    error: &str,
+    settings: &Settings,
+    console: &Console,
+) -> Result<SearchResult> {
+    let path_str = match path.to_str() {
+        Some(path_str) => path_str,
+        None => {
+            return Err(anyhow::anyhow!("Could not convert path to string."));
+        }
+    };
+
+    if settings.verbose {
+        console.print_error(error, path_str);
+    }
+
+    Ok(SearchResult {
+        content: String::new(),
+        streamed: false,
+        fingerprint: None,
+        matches: Vec::new(),
+        walked_lines: 0,
+    })
+}
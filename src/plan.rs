@@ -0,0 +1,308 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{self, Path, PathBuf},
+};
+
+use crate::{
+    enums::WriteOutcome,
+    hook,
+    interrupt::{self, Interrupted},
+    journal::{self, Journal, Run},
+    lock::RootLock,
+    pattern_matcher::{line_spans, Fingerprint, SearchResult},
+    replacer::{self, Edit},
+    walker::{ALREADY_WRITTEN, PROTECTED_PATH, TRANSACTION_ABORTED},
+    Console, Replacer, Settings,
+};
+
+/// Changes proposed by a dry-run, saved with --save-plan
+/// and applied later with `fnr apply`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Plan {
+    pub pattern: String,
+    pub replacement: String,
+    /// Absolute path that was searched, files outside of it are never written
+    pub root: PathBuf,
+    pub changes: Vec<PlannedChange>,
+}
+
+/// A single line to rewrite
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlannedChange {
+    /// Derived from the path, line and old text, so it does not depend
+    /// on the order files are walked in
+    pub id: String,
+    /// Relative to the root, so the plan can be applied from any directory.
+    /// The file name if the root is the file itself.
+    pub path: PathBuf,
+    /// 1-based line number
+    pub line: usize,
+    /// Byte offsets of the line in the file, line ending excluded
+    pub start: usize,
+    pub end: usize,
+    pub old: String,
+    pub new: String,
+    /// Hash of the whole file when the plan was made
+    pub file_hash: String,
+}
+
+impl Plan {
+    pub fn new(pattern: &str, replacement: &str, root: &Path) -> Result<Self> {
+        Ok(Self {
+            pattern: pattern.to_string(),
+            replacement: replacement.to_string(),
+            root: path::absolute(root)?,
+            changes: Vec::new(),
+        })
+    }
+
+    /// Add the changes of the given lines of a searched file
+    pub fn add_file(
+        &mut self,
+        path: &Path,
+        search_result: &SearchResult,
+        line_numbers: &[usize],
+    ) -> Result<()> {
+        let absolute_path = path::absolute(path)?;
+        let path = match absolute_path.strip_prefix(&self.root) {
+            Ok(relative) if !relative.as_os_str().is_empty() => relative,
+            _ => Path::new(absolute_path.file_name().unwrap_or_default()),
+        };
+        let content = &search_result.content;
+        let file_hash = journal::hash(content.as_bytes());
+        let line_spans = line_spans(content);
+
+        for &line in line_numbers {
+            let (start, end) = line_spans[line - 1];
+            let old = &content[start..end];
+
+            self.changes.push(PlannedChange {
+                id: journal::hash(format!("{}\0{}\0{}", path.display(), line, old).as_bytes())
+                    [..12]
+                    .to_string(),
+                path: path.to_path_buf(),
+                line,
+                start,
+                end,
+                old: old.to_string(),
+                new: old.replace(&self.pattern, &self.replacement),
+                file_hash: file_hash.clone(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Where the file of a change is on disk
+    pub fn file_path(&self, change: &PlannedChange) -> PathBuf {
+        // Plans saved before the root was made absolute
+        // hold paths relative to the working directory
+        if self.root.is_relative() {
+            return change.path.clone();
+        }
+
+        match self.root.is_dir() {
+            true => self.root.join(&change.path),
+            false => self.root.clone(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")
+            .with_context(|| format!("Could not write plan {}", path.display()))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Could not read plan {}", path.display()))?;
+
+        serde_json::from_str(&content).with_context(|| format!("Invalid plan {}", path.display()))
+    }
+}
+
+/// `fnr apply PLAN`: write exactly the changes of a plan.
+/// Changes of files that no longer match their hash are rejected.
+/// When resuming an interrupted run, the files it wrote are skipped.
+pub fn apply(
+    plan_path: &Path,
+    settings: Settings,
+    resume: Option<&Run>,
+    console: &Console,
+) -> Result<()> {
+    let plan = Plan::load(plan_path)?;
+    let _lock = RootLock::acquire(&plan.root, settings.lock_timeout, console)?;
+
+    apply_plan(&plan, settings, resume, console)
+}
+
+/// Write the changes of a plan
+pub fn apply_plan(
+    plan: &Plan,
+    settings: Settings,
+    resume: Option<&Run>,
+    console: &Console,
+) -> Result<()> {
+    let transactional = settings.transactional;
+    let after_write = settings.after_write.clone();
+    let revert_on_failure = settings.revert_on_failure;
+
+    // Group changes by file, keeping the plan order
+    let mut changes_by_file: BTreeMap<PathBuf, Vec<&PlannedChange>> = BTreeMap::new();
+    for change in &plan.changes {
+        changes_by_file
+            .entry(plan.file_path(change))
+            .or_default()
+            .push(change);
+    }
+
+    let mut replacer = Replacer::new(plan.root.clone(), settings);
+    if let Some(run) = resume {
+        replacer.resume(Journal::reopen(run)?);
+    }
+    // The files of the plan are the searched files
+    for path in changes_by_file.keys() {
+        if let Some(link_id) = fs::symlink_metadata(path)
+            .ok()
+            .and_then(|m| replacer::link_id(&m))
+        {
+            replacer.add_searched_link(link_id);
+        }
+    }
+    let mut applied = 0;
+    let mut rejected = 0;
+
+    for (path, changes) in changes_by_file {
+        let path = path.as_path();
+        if interrupt::interrupted() {
+            replacer.abort();
+            return Err(Interrupted {
+                run_id: replacer.run_id().map(str::to_string),
+            }
+            .into());
+        }
+
+        let filename = path.to_string_lossy();
+
+        let already_written = resume.is_some_and(|run| {
+            fs::canonicalize(path).is_ok_and(|path| run.original_path(&path).is_some())
+        });
+        if already_written {
+            console.print_skipped_file(&filename, ALREADY_WRITTEN);
+            continue;
+        }
+
+        let checked = match replacer.is_protected(path) {
+            true => Err(PROTECTED_PATH.to_string()),
+            false => check_file(path, &changes),
+        };
+
+        let search_result = match checked {
+            Ok(search_result) => search_result,
+            Err(reason) if transactional => {
+                replacer.abort();
+                anyhow::bail!("{filename}: {reason}. {TRANSACTION_ABORTED}");
+            }
+            Err(reason) => {
+                let ids: Vec<&str> = changes.iter().map(|change| change.id.as_str()).collect();
+                console.print_rejected_changes(&filename, &ids, &reason);
+                rejected += changes.len();
+                continue;
+            }
+        };
+
+        let edits: Vec<Edit> = changes
+            .iter()
+            .map(|change| Edit {
+                start: change.start,
+                end: change.end,
+                new_text: change.new.clone(),
+            })
+            .collect();
+
+        let outcome = replacer.apply_edits(
+            &plan.replacement,
+            &plan.pattern,
+            path,
+            &search_result,
+            &edits,
+        );
+        let outcome = match outcome {
+            Err(e) if transactional => {
+                replacer.abort();
+                return Err(e.context(TRANSACTION_ABORTED));
+            }
+            outcome => outcome?,
+        };
+
+        match outcome {
+            WriteOutcome::Written | WriteOutcome::Staged => applied += changes.len(),
+            WriteOutcome::Skipped(reason) if transactional => {
+                replacer.abort();
+                anyhow::bail!("{filename}: {reason}. {TRANSACTION_ABORTED}");
+            }
+            WriteOutcome::Skipped(reason) | WriteOutcome::Ignored(reason) => {
+                console.print_skipped_file(&filename, &reason);
+                rejected += changes.len();
+            }
+        }
+    }
+
+    if transactional {
+        replacer.commit_all(&plan.replacement, &plan.pattern)?;
+    }
+    replacer.finish()?;
+
+    console.print_apply_summary(applied, rejected);
+    if let Some(run_id) = replacer.run_id() {
+        console.print_run_id(run_id);
+    }
+
+    if let (Some(command), Some(run_id)) = (&after_write, replacer.run_id()) {
+        hook::after_write(command, Some(run_id), revert_on_failure, console)?;
+    }
+
+    Ok(())
+}
+
+/// Read a file of the plan, the error is the reason to reject its changes
+fn check_file(path: &Path, changes: &[&PlannedChange]) -> Result<SearchResult, String> {
+    let metadata = fs::metadata(path).map_err(|e| e.to_string())?;
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+
+    let file_hash = journal::hash(content.as_bytes());
+    if changes.iter().any(|change| change.file_hash != file_hash) {
+        return Err("file changed since the plan was made".to_string());
+    }
+
+    // Offsets edited by hand must still point to the old text
+    if changes
+        .iter()
+        .any(|change| content.get(change.start..change.end) != Some(change.old.as_str()))
+    {
+        return Err("offsets do not match the old text".to_string());
+    }
+
+    // Changes edited by hand may rewrite the same text twice
+    let mut spans: Vec<(usize, usize)> = changes
+        .iter()
+        .map(|change| (change.start, change.end))
+        .collect();
+    spans.sort();
+    if spans
+        .windows(2)
+        .any(|pair| pair[1].0 < pair[0].1 || pair[1] == pair[0])
+    {
+        return Err("changes overlap or are duplicated".to_string());
+    }
+
+    Ok(SearchResult {
+        content,
+        streamed: false,
+        fingerprint: Some(Fingerprint::new(&metadata)),
+        matches: Vec::new(),
+        walked_lines: 0,
+    })
+}
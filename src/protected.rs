@@ -0,0 +1,57 @@
+use anyhow::{Context, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::path::Path;
+
+/// Files never written by default: lockfiles and vendored code.
+/// Replaced by the globs given to --protect.
+pub const DEFAULT_PROTECTED_GLOBS: &[&str] = &[
+    "Cargo.lock",
+    "package-lock.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "poetry.lock",
+    "Gemfile.lock",
+    "composer.lock",
+    "go.sum",
+    "**/vendor/**",
+    "**/node_modules/**",
+];
+
+/// Paths that are never written, even if they match.
+/// Like in a .gitignore, a glob without `/` matches the file name at any depth,
+/// other globs match the path relative to the search root.
+#[derive(Debug, Clone, Default)]
+pub struct ProtectedPaths {
+    names: GlobSet,
+    paths: GlobSet,
+}
+
+impl ProtectedPaths {
+    pub fn new<S: AsRef<str>>(globs: &[S]) -> Result<Self> {
+        let mut names = GlobSetBuilder::new();
+        let mut paths = GlobSetBuilder::new();
+
+        for glob in globs {
+            let glob = glob.as_ref();
+            let compiled =
+                Glob::new(glob).with_context(|| format!("Invalid protected glob {glob}"))?;
+            match glob.contains('/') {
+                true => paths.add(compiled),
+                false => names.add(compiled),
+            };
+        }
+
+        Ok(Self {
+            names: names.build()?,
+            paths: paths.build()?,
+        })
+    }
+
+    /// `relative_path` is the path of the file relative to the search root
+    pub fn is_protected(&self, relative_path: &Path) -> bool {
+        relative_path
+            .file_name()
+            .is_some_and(|name| self.names.is_match(name))
+            || self.paths.is_match(relative_path)
+    }
+}
@@ -1,44 +1,1013 @@
 use anyhow::{Context, Result};
 use std::{
-    fs::File,
-    io::{BufWriter, Read, Write},
-    path::PathBuf,
+    collections::HashMap,
+    fs::{self, File, FileTimes, Metadata},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
 };
+use tempfile::TempPath;
 
-pub struct Replacer {}
+use crate::{
+    audit::{AuditLog, LineChange},
+    editorconfig::Style,
+    enums::{SymlinkPolicy, WriteOutcome},
+    interrupt::{self, Interrupted},
+    journal::{self, Journal, JournalEntry},
+    pattern_matcher::{
+        line_spans, split_line_ending, Fingerprint, SearchResult, BOM, STREAM_BUFFER_SIZE,
+    },
+    Settings,
+};
+
+const MODIFIED_SINCE_SEARCH: &str =
+    "file was modified by another program since it was searched, run fnr again";
+const UNSEARCHED_HARD_LINKS: &str =
+    "file has hard links outside of the searched files, use --force to write them all";
+
+/// Replacement of the bytes `start..end` of a file content by `new_text`
+#[derive(Debug, Clone)]
+pub struct Edit {
+    pub start: usize,
+    pub end: usize,
+    pub new_text: String,
+}
+
+/// Edits replacing the old pattern with the new one on the given lines,
+/// `line_numbers` are 1-based, as returned by the `Searcher`.
+fn line_edits(
+    content: &str,
+    line_numbers: &[usize],
+    old_pattern: &str,
+    new_pattern: &str,
+) -> Vec<Edit> {
+    let line_spans = line_spans(content);
+
+    line_numbers
+        .iter()
+        .map(|line_number| {
+            let (start, end) = line_spans[line_number - 1];
+            Edit {
+                start,
+                end,
+                new_text: content[start..end].replace(old_pattern, new_pattern),
+            }
+        })
+        .collect()
+}
+
+/// Copy a file to `writer` line by line, replacing the old pattern with
+/// the new one on the given lines, through a bounded buffer.
+/// `line_numbers` are 1-based and sorted, as returned by the `Searcher`.
+/// Like with `line_edits()`, everything else is kept byte for byte.
+/// Replaced lines are added to `changes`, if given.
+fn stream_edits(
+    file_path: &Path,
+    writer: &mut dyn Write,
+    line_numbers: &[usize],
+    old_pattern: &str,
+    new_pattern: &str,
+    mut changes: Option<&mut Vec<LineChange>>,
+) -> Result<()> {
+    let file =
+        File::open(file_path).with_context(|| format!("Could not open {}", file_path.display()))?;
+    let mut reader = BufReader::with_capacity(STREAM_BUFFER_SIZE, file);
+    let mut line_numbers = line_numbers.iter().peekable();
+    let mut buffer = Vec::new();
+    let mut line_number = 0;
+
+    loop {
+        buffer.clear();
+        if reader.read_until(b'\n', &mut buffer)? == 0 {
+            break;
+        }
+        line_number += 1;
+
+        if line_numbers.next_if_eq(&&line_number).is_none() {
+            writer.write_all(&buffer)?;
+            continue;
+        }
+
+        // Checked by the searcher, unless the file changed since,
+        // which the fingerprint catches before it is replaced.
+        let line = std::str::from_utf8(&buffer)
+            .with_context(|| format!("{} is no longer valid UTF-8", file_path.display()))?;
+        let (bom, line) = match line_number {
+            1 if line.starts_with(BOM) => line.split_at(BOM.len()),
+            _ => ("", line),
+        };
+        let (text, line_ending) = split_line_ending(line);
+        let new_text = text.replace(old_pattern, new_pattern);
+
+        writer.write_all(bom.as_bytes())?;
+        writer.write_all(new_text.as_bytes())?;
+        writer.write_all(line_ending.as_bytes())?;
+
+        if let Some(changes) = changes.as_deref_mut() {
+            changes.push(LineChange {
+                line: line_number,
+                old: text.to_string(),
+                new: new_text,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Lines replaced by edits, for the audit log
+fn edit_changes(content: &str, edits: &[Edit]) -> Vec<LineChange> {
+    let mut sorted_edits: Vec<&Edit> = edits.iter().collect();
+    sorted_edits.sort_by_key(|edit| edit.start);
+
+    let mut line = 1;
+    let mut position = 0;
+
+    sorted_edits
+        .into_iter()
+        .map(|edit| {
+            line += content[position..edit.start].matches('\n').count();
+            position = edit.start;
+            LineChange {
+                line,
+                old: content[edit.start..edit.end].to_string(),
+                new: edit.new_text.clone(),
+            }
+        })
+        .collect()
+}
+
+/// Build the new content of a file, everything outside of the edits
+/// is kept byte for byte.
+fn apply_edits(content: &str, edits: &[Edit]) -> String {
+    let mut sorted_edits: Vec<&Edit> = edits.iter().collect();
+    sorted_edits.sort_by_key(|edit| edit.start);
+
+    let mut updated_content = String::with_capacity(content.len());
+    let mut position = 0;
+
+    for edit in sorted_edits {
+        updated_content.push_str(&content[position..edit.start]);
+        updated_content.push_str(&edit.new_text);
+        position = edit.end;
+    }
+    updated_content.push_str(&content[position..]);
+
+    updated_content
+}
+
+pub struct Replacer {
+    /// Root of the search, used to mirror paths under --backup-dir
+    root: PathBuf,
+    /// Files resolving outside of it are never written
+    canonical_root: Option<PathBuf>,
+    settings: Settings,
+    /// Created when the first file is written
+    journal: Option<Journal>,
+    /// If true, the journal is the one of an interrupted run
+    resumed: bool,
+    /// Opened when the first file is written, with --audit-log
+    audit_log: Option<AuditLog>,
+    /// Written files not recorded in the audit log yet: in --transactional
+    /// mode, they are only recorded once every file is written.
+    unaudited: Vec<(PathBuf, Vec<LineChange>, String, String)>,
+    /// Files staged in --transactional mode, written by `commit_all()`
+    pending: Vec<PendingWrite>,
+    /// Paths of the files already written, by canonical path, and by
+    /// (device, inode) for files with several hard links
+    written: HashMap<PathBuf, PathBuf>,
+    written_links: HashMap<(u64, u64), PathBuf>,
+    /// Number of hard links of each file among the searched files,
+    /// by (device, inode)
+    searched_links: HashMap<(u64, u64), u64>,
+}
+
+/// Changes to write to a file
+enum Changes<'a> {
+    /// Edits of the content read by the searcher
+    Edits(&'a [Edit]),
+    /// Lines to replace in a file streamed by the searcher
+    Lines(&'a [usize]),
+}
+
+/// Content of a file before it is written
+enum Original {
+    /// As read by the searcher
+    Content(String),
+    /// Too large to be kept in memory, still on disk until the file is replaced
+    OnDisk,
+}
+
+/// A file whose new content is staged in a temporary file,
+/// waiting to replace the original one.
+struct PendingWrite {
+    staged: StagedFile,
+    original: Original,
+    hash_after: String,
+    /// Replaced lines, only collected with --audit-log
+    changes: Vec<LineChange>,
+}
 
 impl Replacer {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(root: PathBuf, settings: Settings) -> Self {
+        Self {
+            canonical_root: fs::canonicalize(&root).ok(),
+            root,
+            settings,
+            journal: None,
+            resumed: false,
+            audit_log: None,
+            unaudited: Vec::new(),
+            pending: Vec::new(),
+            written: HashMap::new(),
+            written_links: HashMap::new(),
+            searched_links: HashMap::new(),
+        }
+    }
+
+    /// Id of the journal recorded for this run, if any file was written
+    pub fn run_id(&self) -> Option<&str> {
+        self.journal.as_ref().map(Journal::id)
+    }
+
+    /// `fnr resume`: record the files written in the journal
+    /// of the interrupted run
+    pub fn resume(&mut self, journal: Journal) {
+        self.journal = Some(journal);
+        self.resumed = true;
+    }
+
+    /// Count a searched file with several hard links, see `link_id()`.
+    /// Files whose links were not all searched are only written with --force.
+    pub fn add_searched_link(&mut self, link_id: (u64, u64)) {
+        *self.searched_links.entry(link_id).or_default() += 1;
+    }
+
+    /// Mark the run as complete, once every file is written
+    pub fn finish(&self) -> Result<()> {
+        match &self.journal {
+            Some(journal) => journal.finish(),
+            None => Ok(()),
+        }
+    }
+
+    /// Path to write for a walked file and its canonical path,
+    /// or the outcome of leaving it untouched.
+    /// Symbolic links are only written with --symlinks=follow-write, and
+    /// nothing resolving outside of the search root is ever written.
+    fn write_target(&self, file_path: &Path) -> Result<Result<(PathBuf, PathBuf), WriteOutcome>> {
+        let is_symlink = fs::symlink_metadata(file_path)?.file_type().is_symlink();
+        if is_symlink && self.settings.symlinks != SymlinkPolicy::FollowWrite {
+            return Ok(Err(WriteOutcome::Ignored(
+                "symbolic link, use --symlinks=follow-write to write its target".to_string(),
+            )));
+        }
+
+        let canonical_path = fs::canonicalize(file_path)?;
+        if !self
+            .canonical_root
+            .as_ref()
+            .is_some_and(|root| canonical_path.starts_with(root))
+        {
+            return Ok(Err(WriteOutcome::Skipped(format!(
+                "resolves to {}, outside of the search root",
+                canonical_path.display()
+            ))));
+        }
+
+        // The target of a link is written, so the link itself is kept
+        let write_path = match is_symlink {
+            true => canonical_path.clone(),
+            false => file_path.to_path_buf(),
+        };
+
+        Ok(Ok((write_path, canonical_path)))
+    }
+
+    /// --editorconfig mode: fix the line endings and final newline
+    /// of the new content of a file, as set by its `.editorconfig` files.
+    /// Otherwise the content is kept byte for byte outside of the edits.
+    fn fix_style(&self, file_path: &Path, updated_content: String) -> Result<String> {
+        match self.settings.editorconfig {
+            true => Ok(Style::for_file(file_path)?.apply(&updated_content)),
+            false => Ok(updated_content),
+        }
+    }
+
+    /// Whether a file matches a --protect glob and must never be written
+    pub fn is_protected(&self, file_path: &Path) -> bool {
+        let relative_path = match file_path.strip_prefix(&self.root) {
+            Ok(relative) if !relative.as_os_str().is_empty() => relative,
+            _ => file_path,
+        };
+
+        self.settings.protected.is_protected(relative_path)
     }
 
-    /// Function to open the file and then replace the old line with the new pattern
+    /// Replace the old pattern with the new one on every given line
+    /// of an already read file content, then write the result back
+    /// to disk in a single pass.
+    /// Files streamed by the searcher are streamed again through
+    /// a temporary file instead, in constant memory.
+    /// `line_numbers` are 1-based, as returned by the `Searcher`.
+    /// The file is skipped if it changed since it was searched.
+    /// In --transactional mode the file is only staged, see `commit_all()`.
     pub fn replace(
+        &mut self,
+        new_pattern: &str,
+        old_pattern: &str,
+        file_path: &Path,
+        search_result: &SearchResult,
+        line_numbers: &[usize],
+    ) -> Result<WriteOutcome> {
+        if search_result.streamed {
+            let changes = Changes::Lines(line_numbers);
+            return self.write(new_pattern, old_pattern, file_path, search_result, changes);
+        }
+
+        let edits = line_edits(
+            &search_result.content,
+            line_numbers,
+            old_pattern,
+            new_pattern,
+        );
+
+        self.apply_edits(new_pattern, old_pattern, file_path, search_result, &edits)
+    }
+
+    /// --output-dir mode: write the file with the given lines replaced
+    /// at the same path under the output directory, or copy it as is
+    /// if there is no line to replace. The file itself is left untouched.
+    pub fn write_output(
         &self,
         new_pattern: &str,
         old_pattern: &str,
-        file_path: &PathBuf,
-        line_number: usize,
+        file_path: &Path,
+        search_result: &SearchResult,
+        line_numbers: &[usize],
     ) -> Result<()> {
-        let mut file = File::open(file_path)
-            .with_context(|| format!("Could not open {}", file_path.display()))?;
+        let Some(output_dir) = &self.settings.output_dir else {
+            return Ok(());
+        };
+        let Some(relative_path) = self.relative_path(file_path) else {
+            return Ok(());
+        };
+        let output_path = output_dir.join(relative_path);
+
+        if let Some(parent) = output_path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Could not create {}", parent.display()))?;
+        }
+        self.check_output_target(output_dir, &output_path)?;
+
+        // Binary and non UTF-8 files have no content, they are always copied.
+        if line_numbers.is_empty() {
+            fs::copy(file_path, &output_path)
+                .with_context(|| format!("Could not copy to {}", output_path.display()))?;
+            return Ok(());
+        }
+
+        let edits = line_edits(
+            &search_result.content,
+            line_numbers,
+            old_pattern,
+            new_pattern,
+        );
+        let updated_content =
+            self.fix_style(file_path, apply_edits(&search_result.content, &edits))?;
+
+        fs::write(&output_path, updated_content)
+            .with_context(|| format!("Could not write {}", output_path.display()))?;
+        fs::set_permissions(&output_path, fs::metadata(file_path)?.permissions())?;
+
+        Ok(())
+    }
+
+    /// Apply edits to an already read file content and write the result
+    /// back to disk. Edits must not overlap.
+    /// The file is skipped if it changed since it was searched.
+    /// In --transactional mode the file is only staged, see `commit_all()`.
+    pub fn apply_edits(
+        &mut self,
+        new_pattern: &str,
+        old_pattern: &str,
+        file_path: &Path,
+        search_result: &SearchResult,
+        edits: &[Edit],
+    ) -> Result<WriteOutcome> {
+        let changes = Changes::Edits(edits);
+        self.write(new_pattern, old_pattern, file_path, search_result, changes)
+    }
+
+    fn write(
+        &mut self,
+        new_pattern: &str,
+        old_pattern: &str,
+        file_path: &Path,
+        search_result: &SearchResult,
+        changes: Changes,
+    ) -> Result<WriteOutcome> {
+        let fingerprint = search_result.fingerprint.as_ref();
+
+        // The file actually written, the target of a symbolic link
+        // with --symlinks=follow-write
+        let (write_path, canonical_path) = match self.write_target(file_path)? {
+            Ok(target) => target,
+            Err(outcome) => return Ok(outcome),
+        };
+
+        // A file reached through several paths is only written once
+        if let Some(first_path) = self.written.get(&canonical_path) {
+            return Ok(WriteOutcome::Ignored(format!(
+                "same file as {}, which was already written",
+                first_path.display()
+            )));
+        }
+
+        // Hard links are written in place (see `StagedFile::commit()`),
+        // so the other links of the file already have the new content.
+        let (file_id, links_count) = file_id(&fs::metadata(&write_path)?);
+        if let Some(first_path) = file_id.and_then(|id| self.written_links.get(&id)) {
+            return Ok(WriteOutcome::Ignored(format!(
+                "hard link to {}, which was already written",
+                first_path.display()
+            )));
+        }
+
+        // Writing in place would modify the links outside of the search as well
+        let searched_links = file_id.map_or(0, |id| {
+            self.searched_links.get(&id).copied().unwrap_or_default()
+        });
+        if links_count > 1 && searched_links < links_count && !self.settings.force {
+            return Ok(WriteOutcome::Skipped(UNSEARCHED_HARD_LINKS.to_string()));
+        }
+
+        if fingerprint.is_some_and(|fingerprint| !fingerprint.matches(file_path)) {
+            return Ok(WriteOutcome::Skipped(MODIFIED_SINCE_SEARCH.to_string()));
+        }
+
+        let audited = self.settings.audit_log.is_some();
+        let mut line_changes = Vec::new();
+
+        let (stage, updated_content) = match changes {
+            Changes::Edits(edits) => {
+                if audited {
+                    line_changes = edit_changes(&search_result.content, edits);
+                }
+                let updated_content =
+                    self.fix_style(&write_path, apply_edits(&search_result.content, edits))?;
+                let write_content = |writer: &mut dyn Write| {
+                    writer.write_all(updated_content.as_bytes())?;
+                    Ok(())
+                };
+                let stage = stage(&write_path, write_content, fingerprint, &self.settings)?;
+                (stage, Some(updated_content))
+            }
+            Changes::Lines(line_numbers) => {
+                let write_content = |writer: &mut dyn Write| {
+                    let changes = audited.then_some(&mut line_changes);
+                    stream_edits(
+                        &write_path,
+                        writer,
+                        line_numbers,
+                        old_pattern,
+                        new_pattern,
+                        changes,
+                    )
+                };
+                let stage = stage(&write_path, write_content, fingerprint, &self.settings)?;
+                (stage, None)
+            }
+        };
+
+        let staged = match stage {
+            Stage::Ready(staged) => staged,
+            Stage::Skipped(reason) => return Ok(WriteOutcome::Skipped(reason)),
+        };
+
+        let pending = match updated_content {
+            Some(updated_content) => PendingWrite {
+                staged,
+                original: Original::Content(search_result.content.clone()),
+                hash_after: journal::hash(updated_content.as_bytes()),
+                changes: line_changes,
+            },
+            None => PendingWrite {
+                hash_after: journal::hash_file(&staged.temp_file)?,
+                staged,
+                original: Original::OnDisk,
+                changes: line_changes,
+            },
+        };
+
+        self.written.insert(canonical_path, file_path.to_path_buf());
+        if let (Some(id), true) = (file_id, links_count > 1) {
+            self.written_links.insert(id, file_path.to_path_buf());
+        }
+
+        if self.settings.transactional {
+            self.pending.push(pending);
+            return Ok(WriteOutcome::Staged);
+        }
+
+        let (outcome, _) = self.commit(pending, old_pattern, new_pattern)?;
+        self.flush_audit()?;
+
+        Ok(outcome)
+    }
+
+    /// --transactional mode: write every staged file, or none of them.
+    /// If a file cannot be written, the files already written are restored
+    /// and an error naming the failing file is returned.
+    pub fn commit_all(&mut self, new_pattern: &str, old_pattern: &str) -> Result<()> {
+        let pending = std::mem::take(&mut self.pending);
+
+        // Checking every file first avoids having to roll back in most cases.
+        for pending_write in &pending {
+            if !pending_write.staged.is_unchanged() {
+                anyhow::bail!(
+                    "{}: {}. No file was modified.",
+                    pending_write.staged.path.display(),
+                    MODIFIED_SINCE_SEARCH
+                );
+            }
+        }
+
+        // Written files and the name of their original content in the journal
+        let mut committed: Vec<(PathBuf, String)> = Vec::new();
+
+        for pending_write in pending {
+            if interrupt::interrupted() {
+                self.unaudited.clear();
+                self.rollback(&committed)?;
+                return Err(Interrupted { run_id: None }.into());
+            }
+
+            let path = pending_write.staged.path.clone();
+
+            let reason = match self.commit(pending_write, old_pattern, new_pattern) {
+                Ok((WriteOutcome::Skipped(reason), _)) => reason,
+                Err(e) => format!("{e:#}"),
+                Ok((_, original)) => {
+                    committed.push((path, original));
+                    continue;
+                }
+            };
 
-        let mut file_content = String::new();
+            self.unaudited.clear();
+            self.rollback(&committed)?;
+            anyhow::bail!(
+                "{}: {}. Every written file was restored.",
+                path.display(),
+                reason
+            );
+        }
 
-        file.read_to_string(&mut file_content)?;
+        self.flush_audit()
+    }
+
+    /// Discard the files staged in --transactional mode
+    pub fn abort(&mut self) {
+        // Dropping the staged files removes their temporary files.
+        self.pending.clear();
+    }
+
+    /// Restore the original content of files written by `commit_all()`,
+    /// from the copies saved in the journal
+    fn rollback(&self, committed: &[(PathBuf, String)]) -> Result<()> {
+        let Some(journal) = &self.journal else {
+            return Ok(());
+        };
+
+        let settings = Settings {
+            force: true,
+            ..self.settings.clone()
+        };
+
+        for (path, original) in committed.iter().rev() {
+            let original_path = journal.original_path(original);
+            let write_content = |writer: &mut dyn Write| {
+                io::copy(&mut File::open(&original_path)?, writer)?;
+                Ok(())
+            };
+            write_atomically_with(path, write_content, None, &settings)
+                .with_context(|| format!("Could not restore {}", path.display()))?;
+        }
+
+        // Nothing is left to undo or resume, unless files were
+        // written before the run was resumed
+        if !self.resumed {
+            journal.mark_undone()?;
+        }
+
+        Ok(())
+    }
+
+    /// Replace the original file with its staged content,
+    /// after saving the original content to the backup and the journal.
+    /// Returns the name of the original content in the journal as well.
+    fn commit(
+        &mut self,
+        pending: PendingWrite,
+        old_pattern: &str,
+        new_pattern: &str,
+    ) -> Result<(WriteOutcome, String)> {
+        let file_path = pending.staged.path.clone();
+
+        self.backup(&file_path, &pending.original)?;
+
+        let journal = self.journal(old_pattern, new_pattern)?;
+        let (original, hash_before) = match &pending.original {
+            Original::Content(content) => (
+                journal.save_original(content.as_bytes())?,
+                journal::hash(content.as_bytes()),
+            ),
+            Original::OnDisk => (
+                journal.save_original_file(&file_path)?,
+                journal::hash_file(&file_path)?,
+            ),
+        };
+
+        let outcome = pending.staged.commit()?;
+
+        if let WriteOutcome::Written = outcome {
+            let entry = JournalEntry {
+                path: fs::canonicalize(&file_path)?,
+                hash_before,
+                hash_after: pending.hash_after,
+                original: original.clone(),
+            };
+            self.journal(old_pattern, new_pattern)?.record(&entry)?;
+
+            if self.settings.audit_log.is_some() {
+                self.unaudited.push((
+                    entry.path,
+                    pending.changes,
+                    entry.hash_before,
+                    entry.hash_after,
+                ));
+            }
+        }
+
+        Ok((outcome, original))
+    }
+
+    /// --audit-log mode: record the replacements of the written files
+    fn flush_audit(&mut self) -> Result<()> {
+        let Some(audit_log_path) = &self.settings.audit_log else {
+            return Ok(());
+        };
+        if self.unaudited.is_empty() {
+            return Ok(());
+        }
+
+        let audit_log = match self.audit_log.take() {
+            Some(audit_log) => audit_log,
+            None => AuditLog::open(audit_log_path)?,
+        };
+        let audit_log = self.audit_log.insert(audit_log);
+
+        for (path, changes, hash_before, hash_after) in self.unaudited.drain(..) {
+            audit_log.record(&path, &changes, &hash_before, &hash_after)?;
+        }
 
-        let mut lines: Vec<&str> = file_content.split('\n').collect();
+        Ok(())
+    }
+
+    fn journal(&mut self, old_pattern: &str, new_pattern: &str) -> Result<&mut Journal> {
+        let journal = match self.journal.take() {
+            Some(journal) => journal,
+            None => Journal::create(old_pattern, new_pattern, &self.root)?,
+        };
+
+        Ok(self.journal.insert(journal))
+    }
+
+    /// Save the content of a file before it is modified,
+    /// if --backup or --backup-dir was given.
+    fn backup(&self, file_path: &Path, original: &Original) -> Result<()> {
+        let Some(backup_path) = self.backup_path(file_path) else {
+            return Ok(());
+        };
 
-        let updated_line = lines[line_number - 1].replace(old_pattern, new_pattern);
-        lines[line_number - 1] = &updated_line;
+        if let Some(parent) = backup_path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Could not create {}", parent.display()))?;
+        }
 
-        let updated_content = lines.join("\n");
+        match original {
+            Original::Content(content) => fs::write(&backup_path, content),
+            Original::OnDisk => fs::copy(file_path, &backup_path).map(|_| ()),
+        }
+        .with_context(|| format!("Could not write backup {}", backup_path.display()))
+    }
 
-        // Write the modified content back to the file
-        let mut writer = BufWriter::new(File::create(&file_path)?);
+    /// Refuse an --output-dir target resolving inside the searched tree,
+    /// through a symbolic link for instance, outside of the output directory
+    fn check_output_target(&self, output_dir: &Path, output_path: &Path) -> Result<()> {
+        // The target itself may not exist yet
+        let resolved = match (fs::canonicalize(output_path), output_path.parent()) {
+            (Ok(path), _) => path,
+            (Err(_), Some(parent)) => {
+                fs::canonicalize(parent)?.join(output_path.file_name().unwrap_or_default())
+            }
+            (Err(e), None) => return Err(e.into()),
+        };
 
-        writer.write_all(updated_content.as_bytes())?;
+        let in_searched_tree = self
+            .canonical_root
+            .as_ref()
+            .is_some_and(|root| resolved.starts_with(root));
+        if in_searched_tree && !resolved.starts_with(fs::canonicalize(output_dir)?) {
+            anyhow::bail!(
+                "{} resolves to {}, in the searched tree, --output-dir never writes it.",
+                output_path.display(),
+                resolved.display()
+            );
+        }
 
         Ok(())
     }
+
+    /// Path of a file relative to the search root, used to mirror it
+    /// under --backup-dir or --output-dir
+    fn relative_path<'a>(&self, file_path: &'a Path) -> Option<&'a Path> {
+        match file_path.strip_prefix(&self.root) {
+            Ok(relative) if !relative.as_os_str().is_empty() => Some(relative),
+            // The search root is the file itself
+            _ => file_path.file_name().map(Path::new),
+        }
+    }
+
+    /// Where the backup of `file_path` goes: next to the file with the
+    /// suffix appended, or under --backup-dir at the same path relative
+    /// to the search root.
+    fn backup_path(&self, file_path: &Path) -> Option<PathBuf> {
+        let suffix = self.settings.backup_suffix.as_deref();
+
+        let backup_path = match &self.settings.backup_dir {
+            Some(backup_dir) => backup_dir.join(self.relative_path(file_path)?),
+            None if suffix.is_some() => file_path.to_path_buf(),
+            None => return None,
+        };
+
+        match suffix {
+            Some(suffix) => {
+                let mut backup_path = backup_path.into_os_string();
+                backup_path.push(suffix);
+                Some(PathBuf::from(backup_path))
+            }
+            None => Some(backup_path),
+        }
+    }
+}
+
+/// New content of a file written to a temporary file
+/// in the same directory, ready to replace it.
+struct StagedFile {
+    path: PathBuf,
+    /// Closed until the file is committed, so --transactional runs
+    /// do not keep a file descriptor open per staged file
+    temp_file: TempPath,
+    fingerprint: Option<Fingerprint>,
+    /// If true, the file has several hard links and is overwritten
+    /// instead of replaced, so they all keep pointing to it
+    in_place: bool,
+    preserve_mtime: bool,
+    #[cfg(windows)]
+    read_only: bool,
+}
+
+enum Stage {
+    Ready(StagedFile),
+    /// The file must be left untouched, for the given reason
+    Skipped(String),
+}
+
+/// Write the content to a temporary file next to `file_path`,
+/// carrying over the permissions (and ownership on unix) of the original file.
+/// If a fingerprint is given, the file is only replaced if it still matches it.
+fn stage(
+    file_path: &Path,
+    write_content: impl FnOnce(&mut dyn Write) -> Result<()>,
+    fingerprint: Option<&Fingerprint>,
+    settings: &Settings,
+) -> Result<Stage> {
+    if let Some(reason) = unwritable_reason(file_path, settings)? {
+        return Ok(Stage::Skipped(reason));
+    }
+
+    let metadata = fs::metadata(file_path)
+        .with_context(|| format!("Could not read metadata of {}", file_path.display()))?;
+
+    let directory = match file_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    let temp_file = interrupt::temp_file_in(directory).with_context(|| {
+        format!(
+            "Could not create a temporary file in {}",
+            directory.display()
+        )
+    })?;
+
+    {
+        let mut writer = BufWriter::new(temp_file.as_file());
+        write_content(&mut writer)?;
+        writer.flush()?;
+    }
+
+    if let Some(reason) = copy_metadata(&metadata, file_path, temp_file.as_file(), settings)? {
+        // Dropping the temporary file removes it.
+        return Ok(Stage::Skipped(reason));
+    }
+
+    temp_file.as_file().sync_all()?;
+
+    Ok(Stage::Ready(StagedFile {
+        path: file_path.to_path_buf(),
+        temp_file: temp_file.into_temp_path(),
+        fingerprint: fingerprint.cloned(),
+        in_place: file_id(&metadata).1 > 1,
+        preserve_mtime: settings.preserve_mtime,
+        #[cfg(windows)]
+        read_only: metadata.permissions().readonly(),
+    }))
+}
+
+impl StagedFile {
+    /// Returns true if the original file did not change since it was searched
+    fn is_unchanged(&self) -> bool {
+        self.fingerprint
+            .as_ref()
+            .is_none_or(|fingerprint| fingerprint.matches(&self.path))
+    }
+
+    /// Rename the temporary file over the original one
+    fn commit(self) -> Result<WriteOutcome> {
+        // Checked again right before replacing the file, as writing
+        // the temporary file may have taken a while.
+        if !self.is_unchanged() {
+            return Ok(WriteOutcome::Skipped(MODIFIED_SINCE_SEARCH.to_string()));
+        }
+
+        if self.in_place {
+            let temp_path = self.temp_file.to_path_buf();
+            let outcome = interrupt::uninterruptible(|| self.overwrite());
+            interrupt::untrack_temp_file(&temp_path);
+            return outcome;
+        }
+
+        // Windows refuses to replace a read-only file,
+        // the new file already carries the read-only flag anyway.
+        #[cfg(windows)]
+        if self.read_only {
+            let mut permissions = fs::metadata(&self.path)?.permissions();
+            permissions.set_readonly(false);
+            fs::set_permissions(&self.path, permissions)?;
+        }
+
+        let temp_path = self.temp_file.to_path_buf();
+        self.temp_file
+            .persist(&self.path)
+            .with_context(|| format!("Could not write {}", self.path.display()))?;
+        interrupt::untrack_temp_file(&temp_path);
+
+        Ok(WriteOutcome::Written)
+    }
+
+    /// Copy the temporary file into the original one, keeping its inode.
+    /// Renaming would split the hard links of the file, at the cost of
+    /// atomicity: the file is truncated, then written. A second Ctrl-C
+    /// waits for it, but a crash leaves the file partly written.
+    fn overwrite(self) -> Result<WriteOutcome> {
+        let mut new_content = File::open(&self.temp_file)?;
+        let mut file = File::options()
+            .write(true)
+            .truncate(true)
+            .open(&self.path)
+            .with_context(|| format!("Could not write {}", self.path.display()))?;
+        io::copy(&mut new_content, &mut file)
+            .with_context(|| format!("Could not write {}", self.path.display()))?;
+
+        // Permissions, owner and extended attributes stay the ones of the inode
+        if self.preserve_mtime {
+            let metadata = new_content.metadata()?;
+            let mut times = FileTimes::new().set_modified(metadata.modified()?);
+            if let Ok(accessed) = metadata.accessed() {
+                times = times.set_accessed(accessed);
+            }
+            file.set_times(times)?;
+        }
+        file.sync_all()?;
+
+        Ok(WriteOutcome::Written)
+    }
+}
+
+/// Write the content written by `write_content` to a temporary file
+/// in the same directory, then rename it over the original file.
+/// The original file is either fully replaced or left untouched,
+/// and its permissions (and ownership on unix) are carried over.
+/// If a fingerprint is given, the file is only replaced if it still matches it.
+pub(crate) fn write_atomically_with(
+    file_path: &Path,
+    write_content: impl FnOnce(&mut dyn Write) -> Result<()>,
+    fingerprint: Option<&Fingerprint>,
+    settings: &Settings,
+) -> Result<WriteOutcome> {
+    match stage(file_path, write_content, fingerprint, settings)? {
+        Stage::Ready(staged) => staged.commit(),
+        Stage::Skipped(reason) => Ok(WriteOutcome::Skipped(reason)),
+    }
+}
+
+/// (device, inode) of a file with several hard links
+pub(crate) fn link_id(metadata: &Metadata) -> Option<(u64, u64)> {
+    match file_id(metadata) {
+        (Some(id), links_count) if links_count > 1 => Some(id),
+        _ => None,
+    }
+}
+
+/// (device, inode) of a file and its number of hard links
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> (Option<(u64, u64)>, u64) {
+    use std::os::unix::fs::MetadataExt;
+
+    (Some((metadata.dev(), metadata.ino())), metadata.nlink())
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &Metadata) -> (Option<(u64, u64)>, u64) {
+    (None, 1)
+}
+
+/// Returns why a file must not be written, if any
+fn unwritable_reason(file_path: &Path, settings: &Settings) -> Result<Option<String>> {
+    let metadata = fs::metadata(file_path)
+        .with_context(|| format!("Could not read metadata of {}", file_path.display()))?;
+
+    if metadata.permissions().readonly() && !settings.force {
+        return Ok(Some(
+            "file is read-only, use --force to write it anyway".to_string(),
+        ));
+    }
+
+    Ok(None)
+}
+
+/// Apply the original file's permissions, ownership and optionally
+/// modification time and extended attributes to the new file.
+/// Returns a reason if the new file cannot match the original one.
+fn copy_metadata(
+    metadata: &Metadata,
+    file_path: &Path,
+    new_file: &File,
+    settings: &Settings,
+) -> Result<Option<String>> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{fchown, MetadataExt};
+
+        let new_metadata = new_file.metadata()?;
+        if (new_metadata.uid(), new_metadata.gid()) != (metadata.uid(), metadata.gid())
+            && fchown(new_file, Some(metadata.uid()), Some(metadata.gid())).is_err()
+        {
+            return Ok(Some(
+                "its owner could not be preserved, try with elevated privileges".to_string(),
+            ));
+        }
+    }
+
+    // Set after the owner, since changing it may clear setuid bits.
+    new_file.set_permissions(metadata.permissions())?;
+
+    if settings.preserve_mtime {
+        let mut times = FileTimes::new().set_modified(metadata.modified()?);
+        if let Ok(accessed) = metadata.accessed() {
+            times = times.set_accessed(accessed);
+        }
+        new_file.set_times(times)?;
+    }
+
+    #[cfg(unix)]
+    if settings.preserve_xattrs {
+        for name in xattr::list(file_path)? {
+            if let Some(value) = xattr::get(file_path, &name)? {
+                xattr::FileExt::set_xattr(new_file, &name, &value).with_context(|| {
+                    format!(
+                        "Could not copy extended attribute {} of {}",
+                        name.to_string_lossy(),
+                        file_path.display()
+                    )
+                })?;
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = file_path;
+
+    Ok(None)
 }
@@ -0,0 +1,109 @@
+use anyhow::{Context, Result};
+use std::{collections::HashMap, env, fs, io::Write, process::Command};
+
+use crate::Plan;
+
+/// --review: open the changes of a plan in the user's editor, one numbered
+/// line each, like `git rebase -i`.
+/// Deleting a line drops its change, editing the text after the
+/// `path:line:` prefix overrides the replacement.
+pub fn review(plan: &mut Plan) -> Result<()> {
+    let mut review_file = tempfile::Builder::new()
+        .prefix("fnr-review-")
+        .suffix(".txt")
+        .tempfile()?;
+    review_file.write_all(render(plan).as_bytes())?;
+    review_file.flush()?;
+
+    let editor = editor();
+    let mut words = editor.split_whitespace();
+    let program = words.next().context("The editor command is empty")?;
+
+    let status = Command::new(program)
+        .args(words)
+        .arg(review_file.path())
+        .status()
+        .with_context(|| format!("Could not run the editor `{editor}`"))?;
+    if !status.success() {
+        anyhow::bail!("The editor `{editor}` exited with {status}, no file was modified.");
+    }
+
+    // Editors may replace the file rather than writing to it, read it back by path.
+    let edited = fs::read_to_string(review_file.path())?;
+    apply_review(plan, &edited)
+}
+
+/// $VISUAL, then $EDITOR, like git
+fn editor() -> String {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string())
+}
+
+/// `<number> <path>:<line>: `, the part of a review line that must not be edited
+fn prefix(number: usize, plan: &Plan) -> String {
+    let change = &plan.changes[number - 1];
+    format!("{} {}:{}: ", number, change.path.display(), change.line)
+}
+
+fn render(plan: &Plan) -> String {
+    let mut content = format!(
+        "# Review the replacement of '{}' with '{}'.\n\
+         # Delete a line to skip its change, edit the text after `path:line: ` to override it.\n\
+         # Lines starting with '#' are ignored, delete every line to apply nothing.\n\n",
+        plan.pattern, plan.replacement
+    );
+
+    for (index, change) in plan.changes.iter().enumerate() {
+        content.push_str(&prefix(index + 1, plan));
+        content.push_str(&change.new);
+        content.push('\n');
+    }
+
+    content
+}
+
+/// Keep the changes left in the edited review, with their new text.
+/// Nothing is kept if a line cannot be understood.
+fn apply_review(plan: &mut Plan, edited: &str) -> Result<()> {
+    let mut new_texts: HashMap<usize, String> = HashMap::new();
+
+    for (line_index, line) in edited.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let understood = line
+            .split_once(' ')
+            .and_then(|(number, _)| number.parse::<usize>().ok())
+            .filter(|number| (1..=plan.changes.len()).contains(number))
+            .and_then(|number| {
+                line.strip_prefix(&prefix(number, plan))
+                    .map(|new_text| (number, new_text))
+            });
+
+        let Some((number, new_text)) = understood else {
+            anyhow::bail!(
+                "Line {} of the review was not understood, no file was modified:\n{}",
+                line_index + 1,
+                line
+            );
+        };
+        if new_texts.insert(number, new_text.to_string()).is_some() {
+            anyhow::bail!("Change {number} appears twice in the review, no file was modified.");
+        }
+    }
+
+    plan.changes = std::mem::take(&mut plan.changes)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, mut change)| {
+            change.new = new_texts.remove(&(index + 1))?;
+            Some(change)
+        })
+        .collect();
+
+    Ok(())
+}
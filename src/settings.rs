@@ -1,6 +1,8 @@
 use std::path::PathBuf;
 
-#[derive(Debug, Clone)]
+use crate::{enums::SymlinkPolicy, protected::ProtectedPaths};
+
+#[derive(Debug, Clone, Default)]
 /// Settings applied for a DirectoryPatcher run
 pub struct Settings {
     /// Control verbosity of console's output
@@ -26,4 +28,102 @@ pub struct Settings {
 
     /// If provided, select the replacement(s) you wish to write on disk
     pub select: Option<Vec<usize>>,
+
+    /// If true, also write files that are read-only
+    /// (default: false)
+    pub force: bool,
+    /// If true, written files keep their original modification time
+    /// (default: false)
+    pub preserve_mtime: bool,
+    /// If true, extended attributes are copied to written files (unix only)
+    /// (default: false)
+    pub preserve_xattrs: bool,
+
+    /// If provided, the original content of every written file
+    /// is saved next to it, with this suffix appended
+    pub backup_suffix: Option<String>,
+    /// If provided, backups are saved under this directory,
+    /// mirroring their path relative to the search root
+    pub backup_dir: Option<PathBuf>,
+
+    /// If true, modified files are all written at the end of the run,
+    /// or none of them if one fails (default: false)
+    pub transactional: bool,
+
+    /// If true, every change is confirmed by the user before being written
+    /// (default: false)
+    pub interactive: bool,
+
+    /// If provided, the changes proposed by a dry-run are saved
+    /// to this plan file, to be applied with `fnr apply`
+    pub save_plan: Option<PathBuf>,
+
+    /// If true, the proposed changes are edited in $EDITOR
+    /// and the ones left are written (default: false)
+    pub review: bool,
+
+    /// If true, print a unified diff of the changes
+    /// instead of the default preview (default: false)
+    pub diff: bool,
+    /// Number of unchanged lines around changes in the diff (default: 3)
+    pub diff_context: usize,
+
+    /// If true, only list the files that would be modified,
+    /// the run fails if there is any (default: false)
+    pub check: bool,
+
+    /// --write aborts before writing anything if more files or matches
+    /// would be modified (default: None, no limit)
+    pub max_files: Option<usize>,
+    pub max_matches: Option<usize>,
+    /// Files never written, even if they match
+    pub protected: ProtectedPaths,
+
+    /// If provided, modified files are written under this directory,
+    /// mirroring the searched tree, which is left untouched
+    pub output_dir: Option<PathBuf>,
+    /// If true, files without changes are not copied to the output directory
+    /// (default: false)
+    pub skip_unchanged: bool,
+
+    /// Whether symbolic links are searched and written (default: skipped)
+    pub symlinks: SymlinkPolicy,
+
+    /// If true, the line endings and final newline of written files
+    /// follow their `.editorconfig` files (default: false)
+    pub editorconfig: bool,
+
+    /// Files larger than this many bytes are searched and written
+    /// line by line, in constant memory (default: None, always read whole)
+    pub stream_above: Option<u64>,
+
+    /// If provided, command run once the changes are written
+    pub after_write: Option<String>,
+    /// If true, the modified files are restored if the
+    /// --after-write command fails (default: false)
+    pub revert_on_failure: bool,
+
+    /// If provided, every applied replacement is appended
+    /// to this file as a JSON line
+    pub audit_log: Option<PathBuf>,
+
+    /// Seconds to wait for other runs writing the same tree
+    /// (default: None, wait as long as needed)
+    pub lock_timeout: Option<u64>,
+
+    /// Number of threads searching files
+    /// (default: 0, one per CPU)
+    pub threads: usize,
+
+    /// If true, .gitignore, .ignore and git excludes are applied
+    /// on top of .fnrignore (default: false)
+    pub respect_gitignore: bool,
+    /// Ignore files applied on top of .fnrignore, lowest precedence first:
+    /// the user's ignore file, then the --ignore-file ones
+    pub ignore_files: Vec<PathBuf>,
+
+    /// Only files between these depths below the searched path are searched,
+    /// 1 being the files directly in it (default: None, no limit)
+    pub min_depth: Option<usize>,
+    pub max_depth: Option<usize>,
 }
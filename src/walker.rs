@@ -1,15 +1,48 @@
-use crate::enums::Operation;
-use crate::{Console, Replacer, Searcher, Settings};
+use crate::audit::AuditLog;
+use crate::enums::{Answer, Operation, SymlinkPolicy, WriteOutcome};
+use crate::interrupt::{self, Interrupted};
+use crate::journal::{Journal, Run};
+use crate::lock::RootLock;
+use crate::{
+    diff, hook, plan, replacer, review, Console, Plan, Replacer, SearchResult, Searcher, Settings,
+};
 use anyhow::{Context, Result};
 use colored::Colorize;
-use ignore::{types::TypesBuilder, WalkBuilder};
-use std::path::{Component, Path, PathBuf};
+use ignore::{types::TypesBuilder, DirEntry, WalkBuilder, WalkState};
+use std::{
+    fs,
+    io::Write,
+    path::{Component, Path, PathBuf},
+    process::ExitCode,
+    sync::mpsc,
+};
+use tempfile::NamedTempFile;
+
+pub(crate) const TRANSACTION_ABORTED: &str = "--transactional: no file was modified.";
+pub(crate) const PROTECTED_PATH: &str = "protected path, see --protect";
+pub(crate) const ALREADY_WRITTEN: &str = "already written by the interrupted run";
+const WRITTEN_BY_FNR: &str = "backup, output or audit log written by fnr";
+const LIMITS_HINT: &str = "Raise --max-files / --max-matches, or use --force.";
+
+/// A file of the tree and the result of its search
+struct SearchedFile {
+    path: PathBuf,
+    /// Original content of a file already written by the interrupted run
+    resumed_from: Option<PathBuf>,
+    /// A backup, an output or the audit log, never written
+    written_by_run: bool,
+    /// (device, inode) of a file with several hard links
+    link_id: Option<(u64, u64)>,
+    result: SearchResult,
+}
 
 pub struct Walker {
     old_pattern: String,
     new_pattern: String,
     path: PathBuf,
     settings: Settings,
+    /// Interrupted run finished by `fnr resume`
+    resume: Option<Run>,
 }
 
 impl Walker {
@@ -25,9 +58,17 @@ impl Walker {
             new_pattern,
             path,
             settings,
+            resume: None,
         }
     }
 
+    /// `fnr resume`: finish an interrupted run, its files already
+    /// written are left as they are.
+    pub(crate) fn resuming(mut self, run: Run) -> Self {
+        self.resume = Some(run);
+        self
+    }
+
     /// Returns true if `entry_path` should be skipped by `--omit`.
     /// Supports exact/prefix paths (`tests/assets`) and component matches
     /// (`assets/` anywhere in the walked path).
@@ -60,8 +101,58 @@ impl Walker {
             .any(|window| window == omit_components.as_slice())
     }
 
+    /// Create the --backup-dir and --output-dir directories and the
+    /// --audit-log file if needed and return their canonical paths,
+    /// so their files are never written.
+    fn prepare_written_paths(&self) -> Result<Vec<PathBuf>> {
+        let mut written_paths = Vec::new();
+
+        for dir in [&self.settings.backup_dir, &self.settings.output_dir]
+            .into_iter()
+            .flatten()
+        {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Could not create {}", dir.display()))?;
+            written_paths.push(fs::canonicalize(dir)?);
+        }
+
+        if let Some(audit_log) = &self.settings.audit_log {
+            AuditLog::open(audit_log)?;
+            written_paths.push(fs::canonicalize(audit_log)?);
+        }
+
+        // The searched tree would be mirrored onto itself
+        if let Some(output_dir) = &self.settings.output_dir {
+            if fs::canonicalize(&self.path)?.starts_with(fs::canonicalize(output_dir)?) {
+                anyhow::bail!(
+                    "--output-dir {} is the searched directory or one of its parents, its files would be overwritten.",
+                    output_dir.display()
+                );
+            }
+        }
+
+        Ok(written_paths)
+    }
+
+    /// Returns true if the file at `path` is written by this run: a file
+    /// under the backup or output directory, the audit log or a backup file.
+    fn is_written_by_run(&self, path: &Path, written_paths: &[PathBuf]) -> bool {
+        let is_backup = self.settings.backup_suffix.as_ref().is_some_and(|suffix| {
+            path.file_name()
+                .is_some_and(|name| name.to_string_lossy().ends_with(suffix.as_str()))
+        });
+
+        is_backup
+            || (!written_paths.is_empty()
+                && fs::canonicalize(path).is_ok_and(|path| {
+                    written_paths
+                        .iter()
+                        .any(|written| path.starts_with(written))
+                }))
+    }
+
     /// https://docs.rs/ignore/latest/ignore/types/struct.TypesBuilder.html
-    fn build_walker(&self) -> Result<ignore::Walk> {
+    fn build_walker(&self) -> Result<ignore::WalkParallel> {
         let mut types_builder = TypesBuilder::new();
         types_builder.add_defaults();
 
@@ -101,6 +192,7 @@ impl Walker {
         // Apply CLI omit filters at _ level so omitted directories are
         // not descended into.
         let omit_patterns = self.settings.omit_pattern.clone();
+
         if !omit_patterns.is_empty() {
             walk_builder.filter_entry(move |entry| {
                 !omit_patterns
@@ -109,6 +201,10 @@ impl Walker {
             });
         }
 
+        // Targets are still only written with --symlinks=follow-write,
+        // see `Replacer::write_target()`.
+        walk_builder.follow_links(self.settings.symlinks != SymlinkPolicy::Skip);
+
         // If settings.search_hidden is true, we set ignore to false
         if self.settings.search_hidden {
             walk_builder.hidden(false);
@@ -117,22 +213,321 @@ impl Walker {
         // If the custom .fnrignore file exists, we use it
         walk_builder.add_custom_ignore_filename(".fnrignore");
 
-        // By default the walker used .gitignore if present in the folder
-        // and ignore mentionned patterns.
-        // We don't want that.
-        walk_builder.git_ignore(false);
-        walk_builder.git_global(false);
-        walk_builder.git_exclude(false);
-        walk_builder.ignore(false);
+        // Matched like the global git excludes, with a lower precedence
+        // than .fnrignore and the ignore files found in the tree
+        for ignore_file in &self.settings.ignore_files {
+            let anchored = anchor_ignore_file(ignore_file, &self.path)
+                .with_context(|| format!("Invalid ignore file {}", ignore_file.display()))?;
+            if let Some(e) = walk_builder.add_ignore(anchored.path()) {
+                return Err(e)
+                    .with_context(|| format!("Invalid ignore file {}", ignore_file.display()));
+            }
+        }
+
+        // The ignore files of git and .ignore are only applied with
+        // --respect-gitignore, .fnrignore is applied on top of them.
+        let respect_gitignore = self.settings.respect_gitignore;
+        walk_builder.git_ignore(respect_gitignore);
+        walk_builder.git_global(respect_gitignore);
+        walk_builder.git_exclude(respect_gitignore);
+        walk_builder.ignore(respect_gitignore);
 
-        Ok(walk_builder.build())
+        // Directories below --max-depth are not descended into,
+        // files above --min-depth are skipped by `search_entry()`
+        walk_builder.max_depth(self.settings.max_depth);
+
+        // 0 lets the walker pick a number of threads from the CPUs
+        walk_builder.threads(self.settings.threads);
+
+        Ok(walk_builder.build_parallel())
     }
 
-    pub fn run(&self) -> Result<()> {
-        let console = Console::new();
+    /// Ignore files applied to the walk, for --verbose
+    fn ignore_sources(&self) -> Vec<String> {
+        let mut sources = vec![".fnrignore".to_string()];
+        if self.settings.respect_gitignore {
+            sources.extend(
+                [
+                    ".gitignore",
+                    ".ignore",
+                    ".git/info/exclude",
+                    "global git excludes",
+                ]
+                .map(String::from),
+            );
+        }
+        sources.extend(
+            self.settings
+                .ignore_files
+                .iter()
+                .map(|path| path.display().to_string()),
+        );
+        sources
+    }
+
+    /// Search every file of the tree, in parallel. Files are sorted by path,
+    /// so the output and the match indexes used by --select do not depend
+    /// on the threads or on the file system.
+    fn search_files(&self, console: &Console) -> Result<Vec<SearchedFile>> {
         let walker = self.build_walker()?;
+        // Backups, outputs and the audit log are searched like any file,
+        // so --select indexes stay the same as in the dry-run, but they
+        // are never written in turn.
+        let written_paths = match self.settings.write || self.settings.review {
+            true => self.prepare_written_paths()?,
+            false => Vec::new(),
+        };
+        if self.settings.verbose {
+            console.print_ignore_sources(&self.ignore_sources());
+        }
         let searcher = Searcher::new();
-        let replacer = Replacer::new();
+        let (sender, receiver) = mpsc::channel();
+
+        walker.run(|| {
+            let sender = sender.clone();
+            let searcher = &searcher;
+            let written_paths = &written_paths;
+            Box::new(move |entry| {
+                let searched = self.search_entry(entry, searcher, written_paths, console);
+                let failed = searched.is_err();
+                if let Some(searched) = searched.transpose() {
+                    // Only fails once the receiver is gone
+                    let _ = sender.send(searched);
+                }
+
+                // Stop as soon as the run is going to fail, or on Ctrl-C
+                match failed || interrupt::interrupted() {
+                    true => WalkState::Quit,
+                    false => WalkState::Continue,
+                }
+            })
+        });
+        drop(sender);
+
+        let mut files = receiver.into_iter().collect::<Result<Vec<_>>>()?;
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(files)
+    }
+
+    /// Search a single entry of the tree, None if it is not a file
+    fn search_entry(
+        &self,
+        entry: Result<DirEntry, ignore::Error>,
+        searcher: &Searcher,
+        written_paths: &[PathBuf],
+        console: &Console,
+    ) -> Result<Option<SearchedFile>> {
+        let entry = entry.with_context(|| {
+            "Could not read directory entry. Maybe try with elevated privileges ?".red()
+        })?;
+
+        let is_file = entry
+            .file_type()
+            .is_some_and(|file_type| file_type.is_file());
+        let too_shallow = self
+            .settings
+            .min_depth
+            .is_some_and(|min_depth| entry.depth() < min_depth);
+        if !is_file || too_shallow {
+            return Ok(None);
+        }
+        // Links followed to a file are not another link of it
+        let link_id = match entry.path_is_symlink() {
+            true => None,
+            false => entry.metadata().ok().and_then(|m| replacer::link_id(&m)),
+        };
+        let path = entry.into_path();
+
+        // Files already written by the interrupted run are searched
+        // in their original content, so --select indexes stay the same.
+        let resumed_from = self.resume.as_ref().and_then(|run| {
+            fs::canonicalize(&path)
+                .ok()
+                .and_then(|path| run.original_path(&path))
+        });
+        let mut result = searcher.lookup(
+            resumed_from.as_ref().unwrap_or(&path),
+            &self.old_pattern,
+            &self.settings,
+            console,
+        )?;
+
+        // Kept until the whole tree is searched, only for the files
+        // written, diffed or planned
+        let settings = &self.settings;
+        let content_needed =
+            settings.write || settings.diff || settings.save_plan.is_some() || settings.review;
+        if result.matches.is_empty() || !content_needed {
+            result.content = String::new();
+        }
+
+        Ok(Some(SearchedFile {
+            written_by_run: self.is_written_by_run(&path, written_paths),
+            link_id,
+            path,
+            resumed_from,
+            result,
+        }))
+    }
+
+    /// --output-dir mode: copy a file without changes, unless --skip-unchanged
+    fn copy_unchanged(
+        &self,
+        replacer: &Replacer,
+        file_path: &Path,
+        result: &SearchResult,
+    ) -> Result<()> {
+        if self.settings.output_dir.is_none() || self.settings.skip_unchanged {
+            return Ok(());
+        }
+
+        replacer.write_output(&self.new_pattern, &self.old_pattern, file_path, result, &[])
+    }
+
+    /// Whether a matched line would still match the pattern once replaced,
+    /// because the replacement contains the pattern (`log` -> `log_v2`), or
+    /// recreates it with the surrounding text (`aab`, `ab` -> `a`).
+    /// A second run would replace it again.
+    fn rematches(&self, line: &str) -> bool {
+        let replaced = line.replace(&self.old_pattern, &self.new_pattern);
+        match self.settings.ignore_case {
+            true => replaced.to_lowercase().contains(&self.old_pattern),
+            false => replaced.contains(&self.old_pattern),
+        }
+    }
+
+    /// Stop a --write run after Ctrl-C, between two files, or a --review
+    /// run before its plan is edited
+    fn check_interrupted(&self, replacer: &mut Replacer) -> Result<()> {
+        if !(self.settings.write || self.settings.review) || !interrupt::interrupted() {
+            return Ok(());
+        }
+
+        // Files staged in --transactional mode are discarded
+        replacer.abort();
+        Err(Interrupted {
+            run_id: replacer.run_id().map(str::to_string),
+        }
+        .into())
+    }
+
+    /// Number of files and matches a --write run would modify: the matches
+    /// selected by --select, outside of protected, already written files
+    /// and files written by fnr.
+    /// --interactive may still decline some of them.
+    fn count_selected(&self, files: &[SearchedFile], replacer: &Replacer) -> (usize, usize) {
+        let mut match_index = 0;
+        let mut selected_files = 0;
+        let mut selected_matches = 0;
+
+        for file in files {
+            let first_index = match_index + 1;
+            match_index += file.result.matches.len();
+            if file.resumed_from.is_some()
+                || file.written_by_run
+                || replacer.is_protected(&file.path)
+            {
+                continue;
+            }
+
+            let selected = (first_index..=match_index)
+                .filter(|index| {
+                    self.settings
+                        .select
+                        .as_ref()
+                        .is_none_or(|select| select.contains(index))
+                })
+                .count();
+            if selected > 0 {
+                selected_files += 1;
+                selected_matches += selected;
+            }
+        }
+
+        (selected_files, selected_matches)
+    }
+
+    /// Abort a --write run before anything is written
+    /// if it would modify too many files or matches.
+    fn check_limits(&self, files: usize, matches: usize) -> Result<()> {
+        if let Some(max_files) = self.settings.max_files.filter(|max| files > *max) {
+            anyhow::bail!(
+                "--write would modify more than {max_files} files, no file was modified. {LIMITS_HINT}"
+            );
+        }
+        if let Some(max_matches) = self.settings.max_matches.filter(|max| matches > *max) {
+            anyhow::bail!(
+                "--write would replace more than {max_matches} matches, no file was modified. {LIMITS_HINT}"
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Apply every selected replacement of a file at once, reusing
+    /// the content already read by the searcher.
+    /// Returns the number of replaced matches.
+    fn write_file(
+        &self,
+        replacer: &mut Replacer,
+        console: &Console,
+        file_path: &Path,
+        result: &SearchResult,
+        lines_to_replace: &[usize],
+    ) -> Result<usize> {
+        let filename = file_path.to_string_lossy();
+        let outcome = replacer.replace(
+            &self.new_pattern,
+            &self.old_pattern,
+            file_path,
+            result,
+            lines_to_replace,
+        );
+
+        // In --transactional mode, staged files are discarded
+        // as soon as one file cannot be written.
+        let outcome = match outcome {
+            Err(e) if self.settings.transactional => {
+                replacer.abort();
+                return Err(e.context(TRANSACTION_ABORTED));
+            }
+            outcome => outcome?,
+        };
+
+        match outcome {
+            WriteOutcome::Written | WriteOutcome::Staged => Ok(lines_to_replace.len()),
+            WriteOutcome::Skipped(reason) if self.settings.transactional => {
+                replacer.abort();
+                anyhow::bail!("{filename}: {reason}. {TRANSACTION_ABORTED}");
+            }
+            WriteOutcome::Skipped(reason) | WriteOutcome::Ignored(reason) => {
+                console.print_skipped_file(&filename, &reason);
+                Ok(0)
+            }
+        }
+    }
+
+    /// Returns a failure exit code in --check mode
+    /// when replacements would be made.
+    pub fn run(&self) -> Result<ExitCode> {
+        let console = Console::new();
+
+        // Held until the end of the run, so concurrent runs never
+        // interleave reads and writes of the same files
+        let _lock = match self.settings.write || self.settings.review {
+            true => Some(RootLock::acquire(
+                &self.path,
+                self.settings.lock_timeout,
+                &console,
+            )?),
+            false => None,
+        };
+
+        let mut replacer = Replacer::new(self.path.clone(), self.settings.clone());
+        if let Some(run) = &self.resume {
+            replacer.resume(Journal::reopen(run)?);
+        }
 
         let mut total_found_matches = 0;
         let mut total_replaced_matches = 0;
@@ -142,94 +537,233 @@ impl Walker {
 
         let mut selected_matches_count = 0;
 
-        for entry in walker {
-            let entry = entry.with_context(|| {
-                "Could not read directory entry. Maybe try with elevated privileges ?".red()
-            })?;
+        // Changes proposed by this dry-run, saved with --save-plan
+        // or edited with --review
+        let mut plan = (self.settings.save_plan.is_some() || self.settings.review)
+            .then(|| Plan::new(&self.old_pattern, &self.new_pattern, &self.path))
+            .transpose()?;
 
-            if let Some(file_type) = entry.file_type() {
-                if file_type.is_file() {
-                    let file_path = entry.path().to_path_buf();
-                    let (matches, lines_walked) =
-                        searcher.lookup(&file_path, &self.old_pattern, &self.settings, &console)?;
+        // With --review, --diff and --check, changes are not printed as they are found
+        let print_changes = !self.settings.review && !self.settings.diff && !self.settings.check;
 
-                    // We increment the total lines walked now, because even without matches
-                    // we get the counter
-                    total_lines_walked += lines_walked;
+        // --check mode counters
+        let mut check_replacements = 0;
+        let mut check_files = 0;
 
-                    if matches.is_empty() {
-                        continue;
-                    }
+        // Replaced lines that would still match the pattern
+        let mut rematched_lines = 0;
+
+        // --interactive mode counters, and whether the user asked to stop
+        let mut accepted_matches = 0;
+        let mut declined_matches = 0;
+        let mut quit = false;
+
+        let searched_files = self.search_files(&console)?;
+        for link_id in searched_files.iter().filter_map(|file| file.link_id) {
+            replacer.add_searched_link(link_id);
+        }
+
+        // The whole tree is searched, the --max-files and --max-matches
+        // limits are checked before the first file is written
+        if self.settings.write {
+            let (files, matches) = self.count_selected(&searched_files, &replacer);
+            self.check_limits(files, matches)?;
+        }
+
+        for SearchedFile {
+            path: file_path,
+            resumed_from,
+            written_by_run,
+            result,
+            ..
+        } in searched_files
+        {
+            self.check_interrupted(&mut replacer)?;
+
+            // We increment the total lines walked now, because even without matches
+            // we get the counter
+            total_lines_walked += result.walked_lines;
+
+            if written_by_run {
+                // Keep --select indexes the same as in the dry-run
+                total_found_matches += result.matches.len();
+                match_index += result.matches.len();
+                if !result.matches.is_empty() {
+                    console.print_skipped_file(&file_path.to_string_lossy(), WRITTEN_BY_FNR);
+                }
+                continue;
+            }
+
+            if result.matches.is_empty() {
+                self.copy_unchanged(&replacer, &file_path, &result)?;
+                continue;
+            }
 
-                    // Count all matches found in this file.
-                    total_found_matches += matches.len();
+            // Count all matches found in this file.
+            total_found_matches += result.matches.len();
 
-                    let filename = entry.path().to_string_lossy();
-                    console.print_file_header(&filename);
+            let filename = file_path.to_string_lossy();
 
-                    for (line_number, line) in &matches {
-                        match_index += 1;
-                        // If the query is a lookup, we print the lookup
-                        // without the changes
-                        if self.settings.lookup {
-                            console.print_lookup(
+            if self.settings.write && replacer.is_protected(&file_path) {
+                // Keep --select indexes the same as in the dry-run
+                match_index += result.matches.len();
+                console.print_skipped_file(&filename, PROTECTED_PATH);
+                self.copy_unchanged(&replacer, &file_path, &result)?;
+                continue;
+            }
+
+            if resumed_from.is_some() {
+                match_index += result.matches.len();
+                console.print_skipped_file(&filename, ALREADY_WRITTEN);
+                continue;
+            }
+
+            if print_changes {
+                console.print_file_header(&filename);
+            }
+
+            // Lines of this file to rewrite, applied once the
+            // whole file has been processed.
+            let mut lines_to_replace = Vec::new();
+            // Set when the user accepts all the remaining changes of this file
+            let mut accept_all_in_file = false;
+
+            for (line_number, line) in &result.matches {
+                match_index += 1;
+                // If the query is a lookup, we print the lookup
+                // without the changes
+                if self.settings.lookup {
+                    console.print_lookup(line, &self.old_pattern, line_number, match_index);
+
+                    continue;
+                }
+                match self.settings.write {
+                    true => {
+                        // We check if the user has selected specific replacements
+                        // If this match is not included, we continue the loop
+                        match &self.settings.select {
+                            Some(select) if !select.contains(&match_index) => {
+                                continue;
+                            }
+                            _ => {}
+                        }
+
+                        if self.settings.interactive && !accept_all_in_file {
+                            console.print_changes(
                                 line,
                                 &self.old_pattern,
-                                &line_number,
+                                &self.new_pattern,
+                                line_number,
                                 match_index,
+                                &false,
                             );
 
-                            continue;
-                        }
-                        match self.settings.write {
-                            true => {
-                                // We check if the user has selected specific replacements
-                                // If this match is not included, we continue the loop
-                                match &self.settings.select {
-                                    Some(select) if !select.contains(&match_index) => {
-                                        continue;
-                                    }
-                                    _ => {}
+                            match console.ask_confirmation()? {
+                                Answer::Yes => {}
+                                Answer::No => {
+                                    declined_matches += 1;
+                                    continue;
                                 }
-
-                                // In write mode, apply the replacement to disk.
-                                replacer.replace(
-                                    &self.new_pattern,
-                                    &self.old_pattern,
-                                    &file_path,
-                                    *line_number,
-                                )?;
-                                total_replaced_matches += 1;
-                            }
-                            false => {
-                                // If the user provide a select list
-                                // Elements that are not in the select
-                                let match_must_be_greyed = self
-                                    .settings
-                                    .select
-                                    .as_ref()
-                                    .is_some_and(|select| !select.contains(&match_index));
-
-                                if match_must_be_greyed == false
-                                    && self.settings.select.as_ref().is_some()
-                                {
-                                    selected_matches_count += 1;
+                                Answer::All => accept_all_in_file = true,
+                                Answer::Quit => {
+                                    quit = true;
+                                    break;
                                 }
-
-                                // In dry-run mode, only print the proposed change.
-                                console.print_changes(
-                                    line,
-                                    &self.old_pattern,
-                                    &self.new_pattern,
-                                    &line_number,
-                                    match_index,
-                                    &match_must_be_greyed,
-                                );
                             }
                         }
+
+                        accepted_matches += 1;
+                        lines_to_replace.push(*line_number);
+                        rematched_lines += usize::from(self.rematches(line));
+                    }
+                    false => {
+                        // If the user provide a select list
+                        // Elements that are not in the select
+                        let match_must_be_greyed = self
+                            .settings
+                            .select
+                            .as_ref()
+                            .is_some_and(|select| !select.contains(&match_index));
+
+                        if !match_must_be_greyed && self.settings.select.is_some() {
+                            selected_matches_count += 1;
+                        }
+
+                        if !match_must_be_greyed {
+                            lines_to_replace.push(*line_number);
+                            rematched_lines += usize::from(self.rematches(line));
+                        }
+
+                        // In dry-run mode, only print the proposed change.
+                        if print_changes {
+                            console.print_changes(
+                                line,
+                                &self.old_pattern,
+                                &self.new_pattern,
+                                line_number,
+                                match_index,
+                                &match_must_be_greyed,
+                            );
+                        }
                     }
                 }
             }
+
+            // In dry-run mode, selected changes only go to the plan
+            // or to the diff
+            if !self.settings.write {
+                if self.settings.check && !lines_to_replace.is_empty() {
+                    console.print_check_file(&filename, lines_to_replace.len());
+                    check_replacements += lines_to_replace.len();
+                    check_files += 1;
+                }
+                if self.settings.diff {
+                    console.print_diff(&diff::unified_diff(
+                        &file_path,
+                        &result.content,
+                        &lines_to_replace,
+                        &self.old_pattern,
+                        &self.new_pattern,
+                        self.settings.diff_context,
+                    ));
+                }
+                if let Some(plan) = plan.as_mut() {
+                    plan.add_file(&file_path, &result, &lines_to_replace)?;
+                }
+                continue;
+            }
+
+            // In write mode, apply every selected replacement of the file
+            // at once, reusing the content already read by the searcher.
+            if self.settings.output_dir.is_some() {
+                match lines_to_replace.is_empty() {
+                    true => self.copy_unchanged(&replacer, &file_path, &result)?,
+                    false => {
+                        replacer.write_output(
+                            &self.new_pattern,
+                            &self.old_pattern,
+                            &file_path,
+                            &result,
+                            &lines_to_replace,
+                        )?;
+                        total_replaced_matches += lines_to_replace.len();
+                    }
+                }
+            } else if !lines_to_replace.is_empty() {
+                self.check_interrupted(&mut replacer)?;
+                total_replaced_matches += self.write_file(
+                    &mut replacer,
+                    &console,
+                    &file_path,
+                    &result,
+                    &lines_to_replace,
+                )?;
+            }
+
+            // The changes accepted before quitting are still written.
+            if quit {
+                break;
+            }
         }
 
         if self.settings.lookup {
@@ -239,13 +773,26 @@ impl Walker {
                 total_lines_walked,
                 &self.settings.select,
                 Operation::Lookup,
+                0,
             );
 
-            return Ok(());
+            return Ok(ExitCode::SUCCESS);
         }
 
         match self.settings.write {
             true => {
+                // After quitting --interactive with Ctrl-C as well
+                self.check_interrupted(&mut replacer)?;
+
+                if self.settings.transactional {
+                    replacer.commit_all(&self.new_pattern, &self.old_pattern)?;
+                }
+                replacer.finish()?;
+
+                if self.settings.interactive {
+                    console.print_interactive_summary(accepted_matches, declined_matches);
+                }
+
                 // NO MATCH AND WRITE IS TRUE
                 if total_replaced_matches == 0 {
                     console.warn_no_replacement_applied(
@@ -261,9 +808,49 @@ impl Walker {
                     total_lines_walked,
                     &self.settings.select,
                     Operation::Replacement,
+                    rematched_lines,
                 );
+
+                if let Some(run_id) = replacer.run_id() {
+                    console.print_run_id(run_id);
+                }
+
+                // Files written to --output-dir are not recorded in a run
+                let written = replacer.run_id().is_some() || self.settings.output_dir.is_some();
+                if let (Some(command), true) = (&self.settings.after_write, written) {
+                    hook::after_write(
+                        command,
+                        replacer.run_id(),
+                        self.settings.revert_on_failure,
+                        &console,
+                    )?;
+                }
             }
             false => {
+                if let (Some(plan), true) = (plan.as_mut(), self.settings.review) {
+                    // The plan is partial if the search was interrupted
+                    self.check_interrupted(&mut replacer)?;
+                    if !plan.changes.is_empty() {
+                        review::review(plan)?;
+                        plan::apply_plan(plan, self.settings.clone(), None, &console)?;
+                        return Ok(ExitCode::SUCCESS);
+                    }
+                }
+
+                // The diff must stay readable by patch tools
+                if self.settings.diff {
+                    return Ok(ExitCode::SUCCESS);
+                }
+
+                if self.settings.check {
+                    console.print_check_summary(check_replacements, check_files);
+
+                    return Ok(match check_replacements {
+                        0 => ExitCode::SUCCESS,
+                        _ => ExitCode::FAILURE,
+                    });
+                }
+
                 // PRINT MATCHES
                 console.print_matches_counts(
                     total_found_matches,
@@ -271,10 +858,72 @@ impl Walker {
                     total_lines_walked,
                     &self.settings.select,
                     Operation::Match,
+                    rematched_lines,
                 );
+
+                if let (Some(plan), Some(plan_path)) = (plan, &self.settings.save_plan) {
+                    plan.save(plan_path)?;
+                    console.print_plan_saved(&plan_path.to_string_lossy(), plan.changes.len());
+                }
             }
         }
 
-        Ok(())
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+/// Copy of an ignore file whose anchored patterns (`/build`, `docs/*.md`)
+/// are anchored to the search root, like the patterns of .fnrignore.
+/// The walker matches the patterns of `add_ignore()` against the paths
+/// as walked, which start with the search root.
+fn anchor_ignore_file(ignore_file: &Path, root: &Path) -> Result<NamedTempFile> {
+    let content = fs::read_to_string(ignore_file)?;
+    let root = root_glob(root);
+
+    let mut anchored = NamedTempFile::new()?;
+    for line in content.lines() {
+        writeln!(anchored, "{}", anchor_pattern(line, &root))?;
+    }
+    anchored.flush()?;
+
+    Ok(anchored)
+}
+
+/// Glob matching the search root at the start of the walked paths, empty
+/// for the current directory (a leading `./` or `/` is stripped before matching)
+fn root_glob(root: &Path) -> String {
+    let parts: Vec<String> = root
+        .components()
+        .filter(|component| !matches!(component, Component::CurDir | Component::RootDir))
+        .map(|component| {
+            component
+                .as_os_str()
+                .to_string_lossy()
+                .chars()
+                .flat_map(|c| match "?*[]{}\\!".contains(c) {
+                    true => vec!['\\', c],
+                    false => vec![c],
+                })
+                .collect()
+        })
+        .collect();
+
+    parts.join("/")
+}
+
+/// Prefix a gitignore pattern with the search root if it is anchored:
+/// like git, if it has a slash other than a trailing one
+fn anchor_pattern(line: &str, root: &str) -> String {
+    if root.is_empty() || line.trim().is_empty() || line.starts_with('#') {
+        return line.to_string();
+    }
+
+    let (negation, pattern) = match line.strip_prefix('!') {
+        Some(pattern) => ("!", pattern),
+        None => ("", line),
+    };
+    match pattern.trim_end().trim_end_matches('/').contains('/') {
+        true => format!("{negation}/{root}/{}", pattern.trim_start_matches('/')),
+        false => line.to_string(),
     }
 }
@@ -0,0 +1,50 @@
+mod common;
+
+#[cfg(all(test, unix))]
+mod tests {
+
+    use crate::common::fnr_in;
+    use anyhow::Result;
+    use std::{fs, str};
+
+    #[test]
+    fn test_after_write_runs_on_written_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::write(dir.path().join("a.txt"), "old\n")?;
+
+        fnr_in(&dir)?
+            .args(["old", "new", ".", "--write"])
+            .args(["--after-write", "grep -q new a.txt"])
+            .arg("--revert-on-failure")
+            .assert()
+            .success();
+
+        assert_eq!(fs::read_to_string(dir.path().join("a.txt"))?, "new\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_revert_on_failure_restores_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::write(dir.path().join("a.txt"), "old\n")?;
+        fs::write(dir.path().join("b.txt"), "keep old\n")?;
+
+        // The command modifies a written file before failing
+        let output = fnr_in(&dir)?
+            .args(["old", "new", ".", "--write"])
+            .args(["--after-write", "echo broken >> a.txt; echo oops; exit 3"])
+            .arg("--revert-on-failure")
+            .output()?;
+
+        assert_eq!(output.status.code(), Some(2));
+        let stderr = str::from_utf8(&output.stderr)?;
+        assert!(stderr.contains("oops"));
+        assert!(stderr.contains("failed with exit code 3, every modified file was restored"));
+
+        assert_eq!(fs::read_to_string(dir.path().join("a.txt"))?, "old\n");
+        assert_eq!(fs::read_to_string(dir.path().join("b.txt"))?, "keep old\n");
+
+        Ok(())
+    }
+}
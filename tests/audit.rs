@@ -0,0 +1,99 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use crate::common::fnr_in;
+    use anyhow::Result;
+    use serde_json::Value;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn read_log(dir: &TempDir) -> Result<Vec<Value>> {
+        fs::read_to_string(dir.path().join("audit.jsonl"))?
+            .lines()
+            .map(|line| Ok(serde_json::from_str(line)?))
+            .collect()
+    }
+
+    #[test]
+    fn test_audit_log_records_every_replacement() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::write(dir.path().join("a.txt"), "old 1\nkeep\nold 2\n")?;
+        // Streamed, as it is larger than --stream-above
+        fs::write(dir.path().join("b.txt"), "keep keep keep\nold 3\n")?;
+
+        fnr_in(&dir)?
+            .args(["old", "new", ".", "--write"])
+            .args(["--audit-log", "audit.jsonl"])
+            .args(["--stream-above", "18"])
+            .assert()
+            .success();
+
+        let records = read_log(&dir)?;
+        assert_eq!(records.len(), 3);
+
+        let changes: Vec<(&str, u64, &str, &str)> = records
+            .iter()
+            .map(|record| {
+                (
+                    record["path"].as_str().unwrap_or_default(),
+                    record["line"].as_u64().unwrap_or_default(),
+                    record["old"].as_str().unwrap_or_default(),
+                    record["new"].as_str().unwrap_or_default(),
+                )
+            })
+            .collect();
+        let a = fs::canonicalize(dir.path().join("a.txt"))?;
+        let b = fs::canonicalize(dir.path().join("b.txt"))?;
+        assert_eq!(
+            changes,
+            [
+                (a.to_str().unwrap_or_default(), 1, "old 1", "new 1"),
+                (a.to_str().unwrap_or_default(), 3, "old 2", "new 2"),
+                (b.to_str().unwrap_or_default(), 2, "old 3", "new 3"),
+            ]
+        );
+
+        let record = &records[0];
+        assert_eq!(record["arguments"][0], "old");
+        assert!(record["timestamp"]
+            .as_str()
+            .is_some_and(|t| t.ends_with('Z')));
+        assert!(record["user"].is_string());
+        assert!(record["working_directory"].is_string());
+        assert_ne!(record["hash_before"], record["hash_after"]);
+
+        // The log is appended to, and never searched itself
+        fnr_in(&dir)?
+            .args(["new", "newer", ".", "--write"])
+            .args(["--audit-log", "audit.jsonl"])
+            .assert()
+            .success();
+        assert_eq!(read_log(&dir)?.len(), 6);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_audit_log_skips_aborted_transactions() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::write(dir.path().join("a.txt"), "old\n")?;
+        fs::write(dir.path().join("b.txt"), "old\n")?;
+
+        // A read-only file aborts the whole run
+        let mut permissions = fs::metadata(dir.path().join("b.txt"))?.permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(dir.path().join("b.txt"), permissions)?;
+
+        fnr_in(&dir)?
+            .args(["old", "new", ".", "--write", "--transactional"])
+            .args(["--audit-log", "audit.jsonl"])
+            .assert()
+            .code(2);
+
+        assert_eq!(read_log(&dir)?.len(), 0);
+
+        Ok(())
+    }
+}
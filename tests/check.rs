@@ -0,0 +1,60 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use crate::common::{fnr, fnr_in};
+    use anyhow::Result;
+    use std::fs;
+
+    #[test]
+    fn test_check_fails_when_replacements_would_happen() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::write(dir.path().join("a.txt"), "old\nold\n")?;
+        fs::write(dir.path().join("b.txt"), "new\n")?;
+
+        fnr_in(&dir)?
+            .arg("old")
+            .arg("new")
+            .arg("--check")
+            .assert()
+            .code(1)
+            .stdout("./a.txt: 2 replacements\n\n2 replacements to make in 1 file.\n");
+
+        // --check never writes
+        assert_eq!(fs::read_to_string(dir.path().join("a.txt"))?, "old\nold\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_succeeds_without_replacement() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::write(dir.path().join("a.txt"), "new\n")?;
+
+        fnr(&dir)?
+            .arg("old")
+            .arg("new")
+            .arg(dir.path())
+            .arg("--check")
+            .assert()
+            .code(0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_error_exit_code() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+
+        fnr(&dir)?
+            .arg("old")
+            .arg("new")
+            .arg(dir.path().join("missing"))
+            .arg("--check")
+            .assert()
+            .code(2);
+
+        Ok(())
+    }
+}
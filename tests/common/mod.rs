@@ -0,0 +1,35 @@
+// Each test file only uses some of the helpers
+#![allow(dead_code)]
+
+use anyhow::Result;
+use assert_cmd::Command;
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+/// Hidden folder of `dir` keeping the journals of the runs,
+/// so tests never touch the user's state directory
+pub fn state_dir(dir: &TempDir) -> PathBuf {
+    dir.path().join(".fnr-state")
+}
+
+/// Hidden folder of `dir` used as the config directory, empty unless
+/// a test writes to it, so the user's config and ignore file never apply
+pub fn config_dir(dir: &TempDir) -> PathBuf {
+    dir.path().join(".fnr-config")
+}
+
+/// fnr command keeping its journals and reading its config in `dir`
+pub fn fnr(dir: &TempDir) -> Result<Command> {
+    let mut command = Command::cargo_bin("fnr")?;
+    command
+        .env("FNR_STATE_DIR", state_dir(dir))
+        .env("FNR_CONFIG_DIR", config_dir(dir));
+    Ok(command)
+}
+
+/// Like `fnr()`, run from `dir`
+pub fn fnr_in(dir: &TempDir) -> Result<Command> {
+    let mut command = fnr(dir)?;
+    command.current_dir(dir.path());
+    Ok(command)
+}
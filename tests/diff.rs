@@ -0,0 +1,77 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use crate::common::fnr_in;
+    use anyhow::Result;
+    use std::{fs, str};
+
+    #[test]
+    fn test_diff_hunks() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let content: String = (1..=12)
+            .map(|n| match n {
+                2 | 4 | 11 => format!("old {n}\n"),
+                _ => format!("line {n}\n"),
+            })
+            .collect();
+        fs::write(dir.path().join("file.txt"), &content)?;
+
+        let output = fnr_in(&dir)?
+            .arg("old")
+            .arg("new")
+            .arg("--diff")
+            .arg("--context")
+            .arg("1")
+            .output()?;
+
+        // Close changes share a hunk, nothing but the diff is printed
+        assert_eq!(
+            str::from_utf8(&output.stdout)?,
+            "--- a/file.txt
++++ b/file.txt
+@@ -1,5 +1,5 @@
+ line 1
+-old 2
++new 2
+ line 3
+-old 4
++new 4
+ line 5
+@@ -10,3 +10,3 @@
+ line 10
+-old 11
++new 11
+ line 12
+"
+        );
+        // A diff never writes
+        assert_eq!(fs::read_to_string(dir.path().join("file.txt"))?, content);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_without_final_newline() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::write(dir.path().join("file.txt"), "first\r\nold")?;
+
+        let output = fnr_in(&dir)?.arg("old").arg("new").arg("--diff").output()?;
+
+        assert_eq!(
+            str::from_utf8(&output.stdout)?,
+            "--- a/file.txt
++++ b/file.txt
+@@ -1,2 +1,2 @@
+ first\r
+-old
+\\ No newline at end of file
++new
+\\ No newline at end of file
+"
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,77 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use crate::common::{config_dir, fnr_in, state_dir};
+    use anyhow::Result;
+    use std::{
+        fs,
+        io::{Read, Write},
+        process::{self, Stdio},
+        str,
+    };
+
+    #[test]
+    fn test_overlapping_runs_wait_for_each_other() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::create_dir_all(dir.path().join("a/sub"))?;
+        fs::create_dir(dir.path().join("b"))?;
+        fs::write(dir.path().join("a/sub/file.txt"), "old\n")?;
+        fs::write(dir.path().join("b/file.txt"), "old\n")?;
+
+        // Holds the lock of a/ while waiting for an answer
+        let mut child = process::Command::new(assert_cmd::cargo::cargo_bin("fnr"))
+            .current_dir(dir.path())
+            .env("FNR_STATE_DIR", state_dir(&dir))
+            .env("FNR_CONFIG_DIR", config_dir(&dir))
+            .args(["old", "new", "a", "--interactive"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let mut stdin = child.stdin.take().expect("piped stdin");
+        let mut stdout = child.stdout.take().expect("piped stdout");
+
+        let mut output = Vec::new();
+        let mut byte = [0];
+        while !String::from_utf8_lossy(&output).contains("Apply this change?") {
+            if stdout.read(&mut byte)? == 0 {
+                anyhow::bail!("fnr exited early");
+            }
+            output.push(byte[0]);
+        }
+
+        // A nested tree is locked
+        let nested = fnr_in(&dir)?
+            .args(["old", "new", "a/sub", "--write", "--lock-timeout", "0"])
+            .output()?;
+        assert_eq!(nested.status.code(), Some(2));
+        assert!(str::from_utf8(&nested.stderr)?.contains("Another fnr run is writing files"));
+        assert_eq!(
+            fs::read_to_string(dir.path().join("a/sub/file.txt"))?,
+            "old\n"
+        );
+
+        // A separate tree is not
+        fnr_in(&dir)?
+            .args(["old", "new", "b", "--write", "--lock-timeout", "0"])
+            .assert()
+            .success();
+        assert_eq!(fs::read_to_string(dir.path().join("b/file.txt"))?, "new\n");
+
+        stdin.write_all(b"y\n")?;
+        assert!(child.wait()?.success());
+        assert_eq!(
+            fs::read_to_string(dir.path().join("a/sub/file.txt"))?,
+            "new\n"
+        );
+
+        // Released once the first run is over
+        fnr_in(&dir)?
+            .args(["new", "newer", "a/sub", "--write", "--lock-timeout", "0"])
+            .assert()
+            .success();
+
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use crate::common::{fnr, fnr_in};
+    use anyhow::Result;
+    use std::{fs, str};
+
+    #[test]
+    fn test_apply_saved_plan() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file_path = dir.path().join("file.txt");
+        let plan_path = dir.path().join(".plan.json");
+        fs::write(&file_path, "old 1\nold 2\nold 3\n")?;
+
+        fnr(&dir)?
+            .arg("old")
+            .arg("new")
+            .arg(dir.path())
+            .arg("--select")
+            .arg("1")
+            .arg("3")
+            .arg("--save-plan")
+            .arg(&plan_path)
+            .assert()
+            .success();
+
+        // A dry-run never writes
+        assert_eq!(fs::read_to_string(&file_path)?, "old 1\nold 2\nold 3\n");
+
+        fnr(&dir)?.arg("apply").arg(&plan_path).assert().success();
+        assert_eq!(fs::read_to_string(&file_path)?, "new 1\nold 2\nnew 3\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_rejects_drifted_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file_path = dir.path().join("file.txt");
+        let plan_path = dir.path().join(".plan.json");
+        fs::write(&file_path, "old 1\n")?;
+
+        fnr(&dir)?
+            .arg("old")
+            .arg("new")
+            .arg(dir.path())
+            .arg("--save-plan")
+            .arg(&plan_path)
+            .assert()
+            .success();
+
+        fs::write(&file_path, "old 1\nold 2\n")?;
+
+        let output = fnr(&dir)?.arg("apply").arg(&plan_path).output()?;
+
+        assert!(output.status.success());
+        assert!(str::from_utf8(&output.stderr)?.contains("file changed since the plan was made"));
+        assert!(str::from_utf8(&output.stdout)?.contains("0 change applied, 1 rejected."));
+        assert_eq!(fs::read_to_string(&file_path)?, "old 1\nold 2\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_from_another_directory() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::create_dir_all(dir.path().join("proj/sub"))?;
+        fs::create_dir(dir.path().join("elsewhere"))?;
+        fs::write(dir.path().join("proj/sub/file.txt"), "old\n")?;
+
+        fnr_in(&dir)?
+            .args(["old", "new", "proj", "--save-plan", "plan.json"])
+            .assert()
+            .success();
+
+        // Paths are saved relative to the absolute root
+        let plan: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(dir.path().join("plan.json"))?)?;
+        assert_eq!(plan["changes"][0]["path"], "sub/file.txt");
+
+        fnr(&dir)?
+            .current_dir(dir.path().join("elsewhere"))
+            .args(["apply", "../plan.json"])
+            .assert()
+            .success();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("proj/sub/file.txt"))?,
+            "new\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_rejects_overlapping_changes() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file_path = dir.path().join("file.txt");
+        let plan_path = dir.path().join(".plan.json");
+        fs::write(&file_path, "old 1\n")?;
+
+        fnr(&dir)?
+            .arg("old")
+            .arg("new")
+            .arg(dir.path())
+            .arg("--save-plan")
+            .arg(&plan_path)
+            .assert()
+            .success();
+
+        // The same change, duplicated by hand
+        let mut plan: serde_json::Value = serde_json::from_str(&fs::read_to_string(&plan_path)?)?;
+        let change = plan["changes"][0].clone();
+        plan["changes"].as_array_mut().unwrap().push(change);
+        fs::write(&plan_path, plan.to_string())?;
+
+        let output = fnr(&dir)?.arg("apply").arg(&plan_path).output()?;
+
+        assert!(output.status.success());
+        assert!(str::from_utf8(&output.stderr)?.contains("changes overlap or are duplicated"));
+        assert!(str::from_utf8(&output.stdout)?.contains("0 change applied, 2 rejected."));
+        assert_eq!(fs::read_to_string(&file_path)?, "old 1\n");
+
+        Ok(())
+    }
+}
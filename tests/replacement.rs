@@ -0,0 +1,805 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use crate::common::{config_dir, fnr, fnr_in, state_dir};
+    use anyhow::Result;
+    use assert_cmd::Command;
+    use std::{fs, str};
+    use tempfile::TempDir;
+
+    /// Create a temporary folder containing a single file with the given content
+    fn setup(filename: &str, content: &str) -> Result<TempDir> {
+        let dir = tempfile::tempdir()?;
+        fs::write(dir.path().join(filename), content)?;
+        Ok(dir)
+    }
+
+    #[test]
+    fn test_write_all_matches_of_a_file() -> Result<()> {
+        let dir = setup("file.txt", "old one\nnothing\nold two\r\nold three\n")?;
+
+        let mut cmd = fnr(&dir)?;
+        cmd.arg("old")
+            .arg("new")
+            .arg(dir.path())
+            .arg("--write")
+            .assert()
+            .success();
+
+        let content = fs::read_to_string(dir.path().join("file.txt"))?;
+        assert_eq!(content, "new one\nnothing\nnew two\r\nnew three\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_selected_matches_only() -> Result<()> {
+        let dir = setup("file.txt", "old one\nold two\nold three")?;
+
+        let mut cmd = fnr(&dir)?;
+        cmd.arg("old")
+            .arg("new")
+            .arg(dir.path())
+            .arg("--write")
+            .arg("--select")
+            .arg("1")
+            .arg("3")
+            .assert()
+            .success();
+
+        let content = fs::read_to_string(dir.path().join("file.txt"))?;
+        assert_eq!(content, "new one\nold two\nnew three");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dry_run_does_not_write() -> Result<()> {
+        let dir = setup("file.txt", "old one\n")?;
+
+        let mut cmd = fnr(&dir)?;
+        cmd.arg("old").arg("new").arg(dir.path()).assert().success();
+
+        let content = fs::read_to_string(dir.path().join("file.txt"))?;
+        assert_eq!(content, "old one\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dry_run_warns_about_cascading_replacements() -> Result<()> {
+        // "log" is in "log_v2", "aabb" becomes "aab" which matches again
+        let dir = setup(
+            "file.txt",
+            "log
+logging
+aabb
+",
+        )?;
+
+        let output = fnr(&dir)?
+            .arg("log")
+            .arg("log_v2")
+            .arg(dir.path())
+            .output()?;
+        assert!(str::from_utf8(&output.stdout)?.contains("Warning: 2 replaced lines"));
+
+        let output = fnr(&dir)?.arg("ab").arg("a").arg(dir.path()).output()?;
+        assert!(str::from_utf8(&output.stdout)?.contains("Warning: 1 replaced line would"));
+
+        // Not when the new text cannot match again
+        let output = fnr(&dir)?
+            .arg("log")
+            .arg("trace")
+            .arg(dir.path())
+            .output()?;
+        assert!(!str::from_utf8(&output.stdout)?.contains("Warning"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_only_file_is_skipped() -> Result<()> {
+        let dir = setup("file.txt", "old one\n")?;
+        let file_path = dir.path().join("file.txt");
+
+        let mut permissions = fs::metadata(&file_path)?.permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&file_path, permissions)?;
+
+        let mut cmd = fnr(&dir)?;
+        let output = cmd
+            .arg("old")
+            .arg("new")
+            .arg(dir.path())
+            .arg("--write")
+            .output()?;
+
+        assert!(output.status.success());
+        assert!(str::from_utf8(&output.stderr)?.contains("file is read-only"));
+        assert_eq!(fs::read_to_string(&file_path)?, "old one\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_only_file_is_written_with_force() -> Result<()> {
+        let dir = setup("file.txt", "old one\n")?;
+        let file_path = dir.path().join("file.txt");
+
+        let mut permissions = fs::metadata(&file_path)?.permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&file_path, permissions)?;
+
+        let mut cmd = fnr(&dir)?;
+        cmd.arg("old")
+            .arg("new")
+            .arg(dir.path())
+            .arg("--write")
+            .arg("--force")
+            .assert()
+            .success();
+
+        assert_eq!(fs::read_to_string(&file_path)?, "new one\n");
+        assert!(fs::metadata(&file_path)?.permissions().readonly());
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_preserves_mode_and_mtime() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        use std::time::{Duration, SystemTime};
+
+        let dir = setup("script.sh", "echo old\n")?;
+        let file_path = dir.path().join("script.sh");
+
+        fs::set_permissions(&file_path, fs::Permissions::from_mode(0o750))?;
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&file_path)?
+            .set_modified(mtime)?;
+
+        let mut cmd = fnr(&dir)?;
+        cmd.arg("old")
+            .arg("new")
+            .arg(dir.path())
+            .arg("--write")
+            .arg("--preserve-mtime")
+            .assert()
+            .success();
+
+        let metadata = fs::metadata(&file_path)?;
+        assert_eq!(fs::read_to_string(&file_path)?, "echo new\n");
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o750);
+        assert_eq!(metadata.modified()?, mtime);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_backup_with_suffix() -> Result<()> {
+        let dir = setup("file.txt", "old one\n")?;
+
+        let mut cmd = fnr(&dir)?;
+        cmd.arg("old")
+            .arg("new")
+            .arg(dir.path())
+            .arg("--write")
+            .arg("--backup=.bak")
+            .assert()
+            .success();
+
+        assert_eq!(
+            fs::read_to_string(dir.path().join("file.txt"))?,
+            "new one\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("file.txt.bak"))?,
+            "old one\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_backup_dir_mirrors_relative_paths() -> Result<()> {
+        let dir = setup("file.txt", "old one\n")?;
+        fs::create_dir(dir.path().join("nested"))?;
+        fs::write(dir.path().join("nested/other.txt"), "old two\n")?;
+        let backup_dir = tempfile::tempdir()?;
+
+        let mut cmd = fnr(&dir)?;
+        cmd.arg("old")
+            .arg("new")
+            .arg(dir.path())
+            .arg("--write")
+            .arg("--backup-dir")
+            .arg(backup_dir.path())
+            .assert()
+            .success();
+
+        assert_eq!(
+            fs::read_to_string(backup_dir.path().join("file.txt"))?,
+            "old one\n"
+        );
+        assert_eq!(
+            fs::read_to_string(backup_dir.path().join("nested/other.txt"))?,
+            "old two\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("nested/other.txt"))?,
+            "new two\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_backups_keep_dry_run_indexes() -> Result<()> {
+        let dir = setup("a.txt", "foo\n")?;
+        for name in ["a.txt.orig", "b.txt", "c.txt"] {
+            fs::write(dir.path().join(name), "foo\n")?;
+        }
+
+        // The backup of a previous run is match [2] of the dry-run
+        let output = fnr_in(&dir)?.args(["foo", "bar", "."]).output()?;
+        let stdout = str::from_utf8(&output.stdout)?;
+        assert!(stdout.contains("./a.txt.orig\n  [2] line 1"));
+        assert!(stdout.contains("./b.txt\n  [3] line 1"));
+
+        fnr_in(&dir)?
+            .args([
+                "foo", "bar", ".", "--write", "--backup", "--select", "2", "3",
+            ])
+            .args(["--audit-log", "audit.log"])
+            .assert()
+            .success();
+
+        // Searched, but never written
+        assert_eq!(fs::read_to_string(dir.path().join("a.txt.orig"))?, "foo\n");
+        assert_eq!(fs::read_to_string(dir.path().join("b.txt"))?, "bar\n");
+        assert_eq!(fs::read_to_string(dir.path().join("b.txt.orig"))?, "foo\n");
+        assert_eq!(fs::read_to_string(dir.path().join("c.txt"))?, "foo\n");
+
+        // Nor is the audit log inside the tree
+        let audit_log = fs::read_to_string(dir.path().join("audit.log"))?;
+        fnr_in(&dir)?
+            .args(["foo", "bar", ".", "--write"])
+            .args(["--audit-log", "audit.log"])
+            .assert()
+            .success();
+        assert!(fs::read_to_string(dir.path().join("audit.log"))?.starts_with(&audit_log));
+        assert_eq!(fs::read_to_string(dir.path().join("a.txt.orig"))?, "bar\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_modified_after_search_is_skipped() -> Result<()> {
+        use fnr::{Console, Replacer, Searcher, Settings, WriteOutcome};
+
+        let dir = setup("file.txt", "old one\n")?;
+        let file_path = dir.path().join("file.txt");
+        let settings = Settings::default();
+
+        let result = Searcher::new().lookup(&file_path, "old", &settings, &Console::new())?;
+
+        // Another program edits the file between the search and the write
+        fs::write(&file_path, "old one, edited\n")?;
+
+        let mut replacer = Replacer::new(dir.path().to_path_buf(), settings);
+        let outcome = replacer.replace("new", "old", &file_path, &result, &[1])?;
+
+        assert!(matches!(outcome, WriteOutcome::Skipped(_)));
+        assert_eq!(fs::read_to_string(&file_path)?, "old one, edited\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_transactional_writes_nothing_if_a_file_fails() -> Result<()> {
+        let dir = setup("a.txt", "old one\n")?;
+        let read_only_path = dir.path().join("b.txt");
+        fs::write(&read_only_path, "old two\n")?;
+
+        let mut permissions = fs::metadata(&read_only_path)?.permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&read_only_path, permissions)?;
+
+        let mut cmd = fnr(&dir)?;
+        let output = cmd
+            .arg("old")
+            .arg("new")
+            .arg(dir.path())
+            .arg("--write")
+            .arg("--transactional")
+            .output()?;
+
+        let stderr = str::from_utf8(&output.stderr)?;
+        assert!(!output.status.success());
+        assert!(stderr.contains("b.txt: file is read-only"));
+        assert!(stderr.contains("no file was modified"));
+        assert_eq!(fs::read_to_string(dir.path().join("a.txt"))?, "old one\n");
+        assert_eq!(fs::read_to_string(&read_only_path)?, "old two\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_transactional_writes_every_file() -> Result<()> {
+        let dir = setup("a.txt", "old one\n")?;
+        fs::write(dir.path().join("b.txt"), "old two\n")?;
+
+        let mut cmd = fnr(&dir)?;
+        cmd.arg("old")
+            .arg("new")
+            .arg(dir.path())
+            .arg("--write")
+            .arg("--transactional")
+            .assert()
+            .success();
+
+        assert_eq!(fs::read_to_string(dir.path().join("a.txt"))?, "new one\n");
+        assert_eq!(fs::read_to_string(dir.path().join("b.txt"))?, "new two\n");
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_transactional_stages_more_files_than_open_file_limit() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        for i in 0..200 {
+            fs::write(dir.path().join(format!("{i}.txt")), "old\n")?;
+        }
+
+        // Staged files must not keep a file descriptor open each
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg("ulimit -n 64 && exec \"$0\" \"$@\"")
+            .arg(assert_cmd::cargo::cargo_bin("fnr"))
+            .args(["old", "new", ".", "--write", "--transactional", "--force"])
+            .current_dir(dir.path())
+            .env("FNR_STATE_DIR", state_dir(&dir))
+            .env("FNR_CONFIG_DIR", config_dir(&dir))
+            .output()?;
+        assert!(
+            output.status.success(),
+            "{}",
+            str::from_utf8(&output.stderr)?
+        );
+
+        for i in 0..200 {
+            assert_eq!(
+                fs::read_to_string(dir.path().join(format!("{i}.txt")))?,
+                "new\n"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_interactive_writes_accepted_changes_only() -> Result<()> {
+        let dir = setup("a.txt", "old 1\nold 2\nold 3\n")?;
+        fs::write(dir.path().join("b.txt"), "old 4\nold 5\n")?;
+        fs::write(dir.path().join("c.txt"), "old 6\n")?;
+
+        let mut cmd = fnr(&dir)?;
+        let output = cmd
+            .arg("old")
+            .arg("new")
+            .arg(dir.path())
+            .arg("--interactive")
+            // a.txt: skip, accept, accept. b.txt: accept all. c.txt: quit
+            .write_stdin("n\ny\ny\na\nq\n")
+            .output()?;
+
+        assert!(output.status.success());
+        assert!(str::from_utf8(&output.stdout)?.contains("4 changes accepted, 1 skipped."));
+        assert_eq!(
+            fs::read_to_string(dir.path().join("a.txt"))?,
+            "old 1\nnew 2\nnew 3\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("b.txt"))?,
+            "new 4\nnew 5\n"
+        );
+        assert_eq!(fs::read_to_string(dir.path().join("c.txt"))?, "old 6\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_pattern_is_refused() -> Result<()> {
+        let dir = setup("file.txt", "old\n")?;
+
+        fnr(&dir)?
+            .arg("")
+            .arg("new")
+            .arg(dir.path())
+            .arg("--write")
+            .assert()
+            .code(2);
+
+        assert_eq!(fs::read_to_string(dir.path().join("file.txt"))?, "old\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_files_aborts_unless_forced() -> Result<()> {
+        let dir = setup("a.txt", "old\n")?;
+        fs::write(dir.path().join("b.txt"), "old\n")?;
+
+        fnr(&dir)?
+            .arg("old")
+            .arg("new")
+            .arg(dir.path())
+            .arg("--write")
+            .arg("--max-files")
+            .arg("1")
+            .assert()
+            .code(2);
+
+        // Nothing is written when the limit is exceeded
+        assert_eq!(fs::read_to_string(dir.path().join("a.txt"))?, "old\n");
+
+        // Only the selected matches count
+        fnr(&dir)?
+            .arg("old")
+            .arg("new")
+            .arg(dir.path())
+            .arg("--write")
+            .arg("--max-files")
+            .arg("1")
+            .arg("--select")
+            .arg("2")
+            .assert()
+            .success();
+        assert_eq!(fs::read_to_string(dir.path().join("a.txt"))?, "old\n");
+        assert_eq!(fs::read_to_string(dir.path().join("b.txt"))?, "new\n");
+        fs::write(dir.path().join("b.txt"), "old\n")?;
+
+        fnr(&dir)?
+            .arg("old")
+            .arg("new")
+            .arg(dir.path())
+            .arg("--write")
+            .arg("--max-files")
+            .arg("1")
+            .arg("--force")
+            .assert()
+            .success();
+
+        assert_eq!(fs::read_to_string(dir.path().join("a.txt"))?, "new\n");
+        assert_eq!(fs::read_to_string(dir.path().join("b.txt"))?, "new\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_protected_paths_are_never_written() -> Result<()> {
+        let dir = setup("Cargo.lock", "old\n")?;
+        fs::create_dir_all(dir.path().join("vendor/lib"))?;
+        fs::write(dir.path().join("vendor/lib/code.rs"), "old\n")?;
+        fs::write(dir.path().join("main.rs"), "old\n")?;
+
+        fnr(&dir)?
+            .arg("old")
+            .arg("new")
+            .arg(dir.path())
+            .arg("--write")
+            .arg("--force")
+            .assert()
+            .success();
+
+        assert_eq!(fs::read_to_string(dir.path().join("Cargo.lock"))?, "old\n");
+        assert_eq!(
+            fs::read_to_string(dir.path().join("vendor/lib/code.rs"))?,
+            "old\n"
+        );
+        assert_eq!(fs::read_to_string(dir.path().join("main.rs"))?, "new\n");
+
+        // Custom globs replace the default ones
+        fnr(&dir)?
+            .arg("new")
+            .arg("newer")
+            .arg(dir.path())
+            .arg("--write")
+            .arg("--protect")
+            .arg("*.rs")
+            .assert()
+            .success();
+
+        assert_eq!(fs::read_to_string(dir.path().join("main.rs"))?, "new\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_output_dir_leaves_source_untouched() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let source = dir.path().join("template");
+        let output = dir.path().join("output");
+        fs::create_dir_all(&source)?;
+        fs::write(source.join("a.txt"), "old\n")?;
+        fs::write(source.join("b.txt"), "nothing\n")?;
+
+        fnr(&dir)?
+            .arg("old")
+            .arg("new")
+            .arg(&source)
+            .arg("--output-dir")
+            .arg(&output)
+            .assert()
+            .success();
+
+        assert_eq!(fs::read_to_string(source.join("a.txt"))?, "old\n");
+        assert_eq!(fs::read_to_string(output.join("a.txt"))?, "new\n");
+        // Files without changes are copied
+        assert_eq!(fs::read_to_string(output.join("b.txt"))?, "nothing\n");
+
+        let skipped_output = dir.path().join("skipped");
+        fnr(&dir)?
+            .arg("old")
+            .arg("new")
+            .arg(&source)
+            .arg("--output-dir")
+            .arg(&skipped_output)
+            .arg("--skip-unchanged")
+            .assert()
+            .success();
+
+        assert!(skipped_output.join("a.txt").exists());
+        assert!(!skipped_output.join("b.txt").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_output_dir_cannot_contain_the_searched_tree() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::create_dir_all(dir.path().join("a/b/b"))?;
+        fs::write(dir.path().join("a/b/x"), "old\n")?;
+        fs::write(dir.path().join("a/b/b/x"), "old nested\n")?;
+
+        // b/x would be mirrored onto a/b/x
+        let output = fnr(&dir)?
+            .current_dir(dir.path())
+            .args(["old", "new", "a/b", "--output-dir", "a"])
+            .output()?;
+        assert_eq!(output.status.code(), Some(2));
+        assert!(str::from_utf8(&output.stderr)?.contains("one of its parents"));
+
+        assert_eq!(fs::read_to_string(dir.path().join("a/b/x"))?, "old\n");
+        assert_eq!(
+            fs::read_to_string(dir.path().join("a/b/b/x"))?,
+            "old nested\n"
+        );
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_output_dir_never_writes_through_links_into_the_tree() -> Result<()> {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempfile::tempdir()?;
+        let source = dir.path().join("source");
+        let output = dir.path().join("output");
+        fs::create_dir_all(source.join("sub"))?;
+        fs::create_dir_all(&output)?;
+        fs::write(source.join("sub/a.txt"), "old\n")?;
+        // Left over in the output directory, pointing back into the tree
+        symlink(source.join("sub"), output.join("sub"))?;
+
+        fnr(&dir)?
+            .arg("old")
+            .arg("new")
+            .arg(&source)
+            .arg("--output-dir")
+            .arg(&output)
+            .assert()
+            .code(2);
+
+        assert_eq!(fs::read_to_string(source.join("sub/a.txt"))?, "old\n");
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks_outside_root_are_never_written() -> Result<()> {
+        use std::os::unix::fs::symlink;
+
+        let outside = setup("secret.txt", "old\n")?;
+        let dir = setup("real.txt", "old\n")?;
+        symlink(
+            outside.path().join("secret.txt"),
+            dir.path().join("out.txt"),
+        )?;
+        symlink(dir.path().join("real.txt"), dir.path().join("in.txt"))?;
+
+        fnr(&dir)?
+            .arg("old")
+            .arg("new")
+            .arg(dir.path())
+            .arg("--write")
+            .arg("--symlinks=follow-write")
+            .assert()
+            .success();
+
+        assert_eq!(
+            fs::read_to_string(outside.path().join("secret.txt"))?,
+            "old\n"
+        );
+        // The target inside the root is written once, the link is kept
+        assert_eq!(fs::read_to_string(dir.path().join("real.txt"))?, "new\n");
+        assert!(fs::symlink_metadata(dir.path().join("in.txt"))?
+            .file_type()
+            .is_symlink());
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hard_links_are_written_once() -> Result<()> {
+        let dir = setup("a.txt", "old\n")?;
+        fs::hard_link(dir.path().join("a.txt"), dir.path().join("b.txt"))?;
+
+        let output = fnr(&dir)?
+            .arg("old")
+            .arg("new")
+            .arg(dir.path())
+            .arg("--write")
+            .output()?;
+
+        assert!(output.status.success());
+        assert!(str::from_utf8(&output.stderr)?.contains("hard link to"));
+        // Still the same file
+        assert_eq!(fs::read_to_string(dir.path().join("a.txt"))?, "new\n");
+        assert_eq!(fs::read_to_string(dir.path().join("b.txt"))?, "new\n");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+
+            let a = fs::metadata(dir.path().join("a.txt"))?;
+            let b = fs::metadata(dir.path().join("b.txt"))?;
+            assert_eq!(a.ino(), b.ino());
+            assert_eq!(a.nlink(), 2);
+        }
+
+        // Undo restores both links as well
+        fnr(&dir)?.arg("undo").assert().success();
+        assert_eq!(fs::read_to_string(dir.path().join("b.txt"))?, "old\n");
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hard_links_outside_root_need_force() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::create_dir_all(dir.path().join("root/a"))?;
+        fs::create_dir(dir.path().join("outside"))?;
+        let outside = dir.path().join("outside/x.txt");
+        fs::write(&outside, "old\n")?;
+        fs::hard_link(&outside, dir.path().join("root/a/hard.txt"))?;
+
+        let output = fnr(&dir)?
+            .arg("old")
+            .arg("new")
+            .arg(dir.path().join("root"))
+            .arg("--write")
+            .output()?;
+
+        assert!(output.status.success());
+        assert!(
+            str::from_utf8(&output.stderr)?.contains("hard links outside of the searched files")
+        );
+        assert_eq!(fs::read_to_string(&outside)?, "old\n");
+
+        fnr(&dir)?
+            .arg("old")
+            .arg("new")
+            .arg(dir.path().join("root"))
+            .arg("--write")
+            .arg("--force")
+            .assert()
+            .success();
+        assert_eq!(fs::read_to_string(&outside)?, "new\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bytes_outside_edits_round_trip() -> Result<()> {
+        // BOM, mixed line endings, lone \r and no final newline
+        let dir = setup(
+            "file.txt",
+            "\u{FEFF}old 1\r\nkeep\n\r\nold 2\rold 3\r\nold 4",
+        )?;
+
+        fnr(&dir)?
+            .arg("old")
+            .arg("new")
+            .arg(dir.path())
+            .arg("--write")
+            .assert()
+            .success();
+
+        assert_eq!(
+            fs::read(dir.path().join("file.txt"))?,
+            "\u{FEFF}new 1\r\nkeep\n\r\nnew 2\rnew 3\r\nnew 4".as_bytes()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_large_files() -> Result<()> {
+        let content = "\u{FEFF}old 1\r\nkeep\n\r\nold 2\rold 3\r\nold 4";
+        let dir = setup("file.txt", content)?;
+
+        fnr(&dir)?
+            .arg("old")
+            .arg("new")
+            .arg(dir.path())
+            .arg("--write")
+            .args(["--select", "1", "3"])
+            .args(["--stream-above", "8"])
+            .assert()
+            .success();
+
+        assert_eq!(
+            fs::read(dir.path().join("file.txt"))?,
+            "\u{FEFF}new 1\r\nkeep\n\r\nold 2\rold 3\r\nnew 4".as_bytes()
+        );
+
+        // The original content is journaled as well
+        fnr(&dir)?.arg("undo").assert().success();
+        assert_eq!(fs::read(dir.path().join("file.txt"))?, content.as_bytes());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_editorconfig_fixes_line_endings() -> Result<()> {
+        let dir = setup("file.txt", "old\r\nkeep\r\nlast")?;
+        fs::write(dir.path().join("untouched.txt"), "keep\r\n")?;
+        fs::write(
+            dir.path().join(".editorconfig"),
+            "root = true\n\n[*.txt]\nend_of_line = lf\ninsert_final_newline = true\n",
+        )?;
+
+        fnr(&dir)?
+            .arg("old")
+            .arg("new")
+            .arg(dir.path())
+            .arg("--write")
+            .arg("--editorconfig")
+            .assert()
+            .success();
+
+        assert_eq!(
+            fs::read_to_string(dir.path().join("file.txt"))?,
+            "new\nkeep\nlast\n"
+        );
+        // Only files written by fnr are fixed
+        assert_eq!(
+            fs::read_to_string(dir.path().join("untouched.txt"))?,
+            "keep\r\n"
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+mod common;
+
+#[cfg(all(test, unix))]
+mod tests {
+
+    use crate::common::{config_dir, fnr_in, state_dir};
+    use anyhow::Result;
+    use std::{
+        fs,
+        io::{Read, Write},
+        path::Path,
+        process::{self, Stdio},
+        str,
+    };
+
+    const PROMPT: &str = "Apply this change?";
+
+    /// Read an output until `text` is printed
+    fn wait_for(output: &mut impl Read, text: &str) -> Result<()> {
+        let mut read = Vec::new();
+        let mut byte = [0];
+        while !String::from_utf8_lossy(&read).contains(text) {
+            if output.read(&mut byte)? == 0 {
+                anyhow::bail!("fnr exited early: {}", String::from_utf8_lossy(&read));
+            }
+            read.push(byte[0]);
+        }
+        Ok(())
+    }
+
+    fn read(path: &Path) -> Result<String> {
+        Ok(fs::read_to_string(path)?)
+    }
+
+    #[test]
+    fn test_interrupted_run_is_resumed() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::write(dir.path().join("a.txt"), "old\n")?;
+        fs::write(dir.path().join("b.txt"), "old\n")?;
+
+        let mut child = process::Command::new(assert_cmd::cargo::cargo_bin("fnr"))
+            .current_dir(dir.path())
+            .env("FNR_STATE_DIR", state_dir(&dir))
+            .env("FNR_CONFIG_DIR", config_dir(&dir))
+            .args(["old", "new", ".", "--interactive"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let mut stdin = child.stdin.take().expect("piped stdin");
+        let mut stdout = child.stdout.take().expect("piped stdout");
+        let mut stderr = child.stderr.take().expect("piped stderr");
+
+        // a.txt is written, then Ctrl-C is pressed on the prompt of b.txt
+        wait_for(&mut stdout, PROMPT)?;
+        stdin.write_all(b"y\n")?;
+        wait_for(&mut stdout, PROMPT)?;
+        process::Command::new("kill")
+            .args(["-INT", &child.id().to_string()])
+            .status()?;
+        wait_for(&mut stderr, "Interrupted")?;
+        stdin.write_all(b"y\n")?;
+
+        assert_eq!(child.wait()?.code(), Some(130));
+        assert_eq!(read(&dir.path().join("a.txt"))?, "new\n");
+        assert_eq!(read(&dir.path().join("b.txt"))?, "old\n");
+
+        let history = fnr_in(&dir)?.arg("history").output()?;
+        assert!(str::from_utf8(&history.stdout)?.contains("incomplete"));
+
+        // Only b.txt is asked about again
+        let output = fnr_in(&dir)?.arg("resume").write_stdin("y\n").output()?;
+        assert!(output.status.success());
+        assert!(str::from_utf8(&output.stderr)?.contains("already written"));
+        assert_eq!(read(&dir.path().join("b.txt"))?, "new\n");
+
+        fnr_in(&dir)?.arg("resume").assert().code(2);
+
+        // Both files belong to the same run
+        fnr_in(&dir)?.arg("undo").assert().success();
+        assert_eq!(read(&dir.path().join("a.txt"))?, "old\n");
+        assert_eq!(read(&dir.path().join("b.txt"))?, "old\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_second_interrupt_removes_temporary_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::write(dir.path().join("a.txt"), "old\n")?;
+        fs::write(dir.path().join("b.txt"), "old\n")?;
+
+        let mut child = process::Command::new(assert_cmd::cargo::cargo_bin("fnr"))
+            .current_dir(dir.path())
+            .env("FNR_STATE_DIR", state_dir(&dir))
+            .env("FNR_CONFIG_DIR", config_dir(&dir))
+            .args(["old", "new", ".", "--interactive", "--transactional"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let mut stdin = child.stdin.take().expect("piped stdin");
+        let mut stdout = child.stdout.take().expect("piped stdout");
+        let mut stderr = child.stderr.take().expect("piped stderr");
+
+        // a.txt is staged in a temporary file, then Ctrl-C is pressed
+        // twice on the prompt of b.txt
+        wait_for(&mut stdout, PROMPT)?;
+        stdin.write_all(b"y\n")?;
+        wait_for(&mut stdout, PROMPT)?;
+        process::Command::new("kill")
+            .args(["-INT", &child.id().to_string()])
+            .status()?;
+        wait_for(&mut stderr, "Interrupted")?;
+        process::Command::new("kill")
+            .args(["-INT", &child.id().to_string()])
+            .status()?;
+
+        assert_eq!(child.wait()?.code(), Some(130));
+        assert_eq!(read(&dir.path().join("a.txt"))?, "old\n");
+        let mut names = fs::read_dir(dir.path())?
+            .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+            .collect::<Result<Vec<_>>>()?;
+        names.sort();
+        assert_eq!(names, [".fnr-state", "a.txt", "b.txt"]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+mod common;
+
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+
+    use crate::common::fnr;
+    use anyhow::Result;
+    use assert_cmd::Command;
+    use std::{fs, os::unix::fs::PermissionsExt, path::PathBuf};
+    use tempfile::TempDir;
+
+    /// fnr command keeping its journals in a hidden folder of `dir`,
+    /// with an "editor" running the given shell script on the review file
+    fn fnr_with_editor(dir: &TempDir, script: &str) -> Result<Command> {
+        let editor: PathBuf = dir.path().join(".editor.sh");
+        fs::write(&editor, format!("#!/bin/sh\n{script}\n"))?;
+        fs::set_permissions(&editor, fs::Permissions::from_mode(0o755))?;
+
+        let mut cmd = fnr(dir)?;
+        cmd.env_remove("VISUAL").env("EDITOR", editor);
+        Ok(cmd)
+    }
+
+    #[test]
+    fn test_review_deselect_and_override() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file_path = dir.path().join("file.txt");
+        fs::write(&file_path, "old 1\nold 2\nold 3\n")?;
+
+        // Drop the second change, override the third one
+        fnr_with_editor(
+            &dir,
+            r#"sed -i -e '/^2 /d' -e 's/: new 3$/: custom 3/' "$1""#,
+        )?
+        .arg("old")
+        .arg("new")
+        .arg(dir.path())
+        .arg("--review")
+        .assert()
+        .success();
+
+        assert_eq!(fs::read_to_string(&file_path)?, "new 1\nold 2\ncustom 3\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_review_invalid_line_writes_nothing() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file_path = dir.path().join("file.txt");
+        fs::write(&file_path, "old 1\nold 2\n")?;
+
+        // The path:line prefix of a change must not be edited
+        fnr_with_editor(&dir, r#"sed -i -e 's/^1 .*$/1 oops/' "$1""#)?
+            .arg("old")
+            .arg("new")
+            .arg(dir.path())
+            .arg("--review")
+            .assert()
+            .failure();
+
+        assert_eq!(fs::read_to_string(&file_path)?, "old 1\nold 2\n");
+
+        Ok(())
+    }
+}
@@ -1,13 +1,16 @@
+mod common;
+
 #[cfg(test)]
 mod tests {
 
+    use crate::common::{config_dir, fnr, fnr_in};
     use anyhow::Result;
-    use assert_cmd::Command;
     use std::str;
 
     #[test]
     fn test_basic_search_empty() -> Result<()> {
-        let mut cmd = Command::cargo_bin("fnr")?;
+        let home = tempfile::tempdir()?;
+        let mut cmd = fnr(&home)?;
         let output = cmd
             .arg("nothing")
             .arg("new")
@@ -19,7 +22,7 @@ mod tests {
         let stderr = str::from_utf8(&output.stderr)?;
 
         assert!(output.status.success());
-        assert_eq!(stdout, "\nNo match found.\n");
+        assert_eq!(stdout, "\nNo match found.\n11 lines scanned.\n");
         assert_eq!(stderr, "");
 
         Ok(())
@@ -27,7 +30,8 @@ mod tests {
 
     #[test]
     fn test_basic_search() -> Result<()> {
-        let mut cmd = Command::cargo_bin("fnr")?;
+        let home = tempfile::tempdir()?;
+        let mut cmd = fnr(&home)?;
         let output = cmd
             .arg("old")
             .arg("new")
@@ -41,7 +45,8 @@ mod tests {
         assert!(output.status.success());
         assert_eq!(
             stdout,
-            "\ntests/assets/classic.txt\n-- old\n++ new\n\n1 match found.\nRe-run the command with --write to write changes to disk.\n");
+            "\ntests/assets/classic.txt\n  [1] line 4\n  -- old\n  ++ new\n\n1 match found.\n11 lines scanned.\nTip: use --write to apply.\n"
+        );
         assert_eq!(stderr, "");
 
         Ok(())
@@ -49,7 +54,8 @@ mod tests {
 
     #[test]
     fn test_basic_search_two_matches_same_file() -> Result<()> {
-        let mut cmd = Command::cargo_bin("fnr")?;
+        let home = tempfile::tempdir()?;
+        let mut cmd = fnr(&home)?;
         let output = cmd
             .arg("here")
             .arg("new")
@@ -63,7 +69,7 @@ mod tests {
         assert!(output.status.success());
         assert_eq!(
             stdout,
-            "\ntests/assets/classic.txt\n-- I appear here\n++ I appear new\n\ntests/assets/classic.txt\n-- and here\n++ and new\n\n2 matches found.\nRe-run the command with --write to write changes to disk.\n");
+            "\ntests/assets/classic.txt\n  [1] line 2\n  -- I appear here\n  ++ I appear new\n  [2] line 5\n  -- and here\n  ++ and new\n\n2 matches found.\n11 lines scanned.\nTip: use --write to apply.\n");
         assert_eq!(stderr, "");
 
         Ok(())
@@ -71,7 +77,8 @@ mod tests {
 
     #[test]
     fn test_basic_search_two_matches_different_files() -> Result<()> {
-        let mut cmd = Command::cargo_bin("fnr")?;
+        let home = tempfile::tempdir()?;
+        let mut cmd = fnr(&home)?;
         let output = cmd
             .arg("world")
             .arg("new")
@@ -85,7 +92,7 @@ mod tests {
         assert!(output.status.success());
         assert_eq!(
             stdout,
-            "\ntests/assets/classic.txt\n-- hello world\n++ hello new\n\ntests/assets/some_python.py\n-- print(\"hello world\")\n++ print(\"hello new\")\n\n2 matches found.\nRe-run the command with --write to write changes to disk.\n");
+            "\ntests/assets/classic.txt\n  [1] line 6\n  -- hello world\n  ++ hello new\n\ntests/assets/some_python.py\n  [2] line 2\n  -- print(\"hello world\")\n  ++ print(\"hello new\")\n\n2 matches found.\n11 lines scanned.\nTip: use --write to apply.\n");
         assert_eq!(stderr, "");
 
         Ok(())
@@ -94,7 +101,8 @@ mod tests {
     // Starting using args
     #[test]
     fn test_basic_search_three_matches_with_hidden_file() -> Result<()> {
-        let mut cmd = Command::cargo_bin("fnr")?;
+        let home = tempfile::tempdir()?;
+        let mut cmd = fnr(&home)?;
         let output = cmd
             .arg("world")
             .arg("new")
@@ -111,7 +119,7 @@ mod tests {
 
         assert_eq!(
             stdout,
-            "\ntests/assets/classic.txt\n-- hello world\n++ hello new\n\ntests/assets/some_python.py\n-- print(\"hello world\")\n++ print(\"hello new\")\n\ntests/assets/.hidden\n-- hello world\n++ hello new\n\n3 matches found.\nRe-run the command with --write to write changes to disk.\n");
+            "\ntests/assets/.hidden\n  [1] line 1\n  -- hello world\n  ++ hello new\n\ntests/assets/classic.txt\n  [2] line 6\n  -- hello world\n  ++ hello new\n\ntests/assets/some_python.py\n  [3] line 2\n  -- print(\"hello world\")\n  ++ print(\"hello new\")\n\n3 matches found.\n13 lines scanned.\nTip: use --write to apply.\n");
         assert_eq!(stderr, "");
 
         Ok(())
@@ -119,7 +127,8 @@ mod tests {
 
     #[test]
     fn test_basic_search_three_matches_with_hidden_file_verbose() -> Result<()> {
-        let mut cmd = Command::cargo_bin("fnr")?;
+        let home = tempfile::tempdir()?;
+        let mut cmd = fnr(&home)?;
         let output = cmd
             .arg("world")
             .arg("new")
@@ -138,15 +147,16 @@ mod tests {
 
         assert_eq!(
             stdout,
-            "\ntests/assets/classic.txt\n-- hello world\n++ hello new\n\ntests/assets/some_python.py\n-- print(\"hello world\")\n++ print(\"hello new\")\n\ntests/assets/.hidden\n-- hello world\n++ hello new\n\n3 matches found.\nRe-run the command with --write to write changes to disk.\n");
-        assert_eq!(stderr, "");
+            "\ntests/assets/.hidden\n  [1] line 1\n  -- hello world\n  ++ hello new\n\ntests/assets/classic.txt\n  [2] line 6\n  -- hello world\n  ++ hello new\n\ntests/assets/some_python.py\n  [3] line 2\n  -- print(\"hello world\")\n  ++ print(\"hello new\")\n\n3 matches found.\n13 lines scanned.\nTip: use --write to apply.\n");
+        assert_eq!(stderr, "Ignore files: .fnrignore\n");
 
         Ok(())
     }
 
     #[test]
     fn test_basic_search_case_insensitive() -> Result<()> {
-        let mut cmd = Command::cargo_bin("fnr")?;
+        let home = tempfile::tempdir()?;
+        let mut cmd = fnr(&home)?;
         let output = cmd
             .arg("WORLD")
             .arg("new")
@@ -163,7 +173,7 @@ mod tests {
 
         assert_eq!(
             stdout,
-            "\ntests/assets/classic.txt\n-- hello world\n++ hello new\n\ntests/assets/some_python.py\n-- print(\"hello world\")\n++ print(\"hello new\")\n\ntests/assets/some_python.py\n-- print(\"hello world\")\n++ print(\"hello new\")\n\n3 matches found.\nRe-run the command with --write to write changes to disk.\n");
+            "\ntests/assets/classic.txt\n  [1] line 6\n  -- hello world\n  ++ hello new\n\ntests/assets/some_python.py\n  [2] line 1\n  -- print(\"hello world\")\n  ++ print(\"hello new\")\n  [3] line 2\n  -- print(\"hello world\")\n  ++ print(\"hello new\")\n\n3 matches found.\n11 lines scanned.\nTip: use --write to apply.\n");
         assert_eq!(stderr, "");
 
         Ok(())
@@ -171,7 +181,8 @@ mod tests {
 
     #[test]
     fn test_basic_search_with_omit() -> Result<()> {
-        let mut cmd = Command::cargo_bin("fnr")?;
+        let home = tempfile::tempdir()?;
+        let mut cmd = fnr(&home)?;
         let output = cmd
             .arg("find")
             .arg("new")
@@ -187,7 +198,7 @@ mod tests {
 
         assert!(output.status.success());
 
-        assert_eq!(stdout, "\nNo match found.\n");
+        assert_eq!(stdout, "\nNo match found.\n10 lines scanned.\n");
         assert_eq!(stderr, "");
 
         Ok(())
@@ -195,7 +206,8 @@ mod tests {
 
     #[test]
     fn test_basic_search_filtering_files() -> Result<()> {
-        let mut cmd = Command::cargo_bin("fnr")?;
+        let home = tempfile::tempdir()?;
+        let mut cmd = fnr(&home)?;
         let output = cmd
             .arg("classic")
             .arg("new")
@@ -211,9 +223,223 @@ mod tests {
 
         assert!(output.status.success());
 
-        assert_eq!(stdout, "\ntests/assets/some_python.py\n-- print(\"classic !\")\n++ print(\"new !\")\n\n1 match found.\nRe-run the command with --write to write changes to disk.\n");
+        assert_eq!(stdout, "\ntests/assets/some_python.py\n  [1] line 3\n  -- print(\"classic !\")\n  ++ print(\"new !\")\n\n1 match found.\n3 lines scanned.\nTip: use --write to apply.\n");
         assert_eq!(stderr, "");
 
         Ok(())
     }
+
+    #[test]
+    fn test_parallel_search_output_is_sorted() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        for name in ["b", "a", "a/c", "a/b", "d"] {
+            std::fs::create_dir_all(dir.path().join(name))?;
+            for file in ["2.txt", "1.txt", "10.txt"] {
+                std::fs::write(dir.path().join(name).join(file), "old\nold\n")?;
+            }
+        }
+
+        let search = |threads: &str| -> Result<String> {
+            let output = fnr_in(&dir)?
+                .args(["old", "new", ".", "--threads", threads])
+                .output()?;
+            assert!(output.status.success());
+            Ok(String::from_utf8(output.stdout)?)
+        };
+
+        let stdout = search("1")?;
+        let headers: Vec<&str> = stdout.lines().filter(|l| l.starts_with("./")).collect();
+        assert_eq!(headers.len(), 15);
+        assert_eq!(
+            &headers[..4],
+            ["./a/1.txt", "./a/10.txt", "./a/2.txt", "./a/b/1.txt"]
+        );
+        assert_eq!(headers[14], "./d/2.txt");
+
+        // Same files, same match indexes
+        for _ in 0..3 {
+            assert_eq!(search("8")?, stdout);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_respect_gitignore() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config_dir = config_dir(&dir);
+        std::fs::create_dir_all(dir.path().join(".git"))?;
+        std::fs::create_dir_all(dir.path().join("target"))?;
+        std::fs::create_dir_all(&config_dir)?;
+        std::fs::write(dir.path().join(".gitignore"), "target/\n")?;
+        std::fs::write(dir.path().join(".fnrignore"), "skipped.txt\n")?;
+        std::fs::write(dir.path().join("target/built.txt"), "old\n")?;
+        std::fs::write(dir.path().join("skipped.txt"), "old\n")?;
+        std::fs::write(dir.path().join("source.txt"), "old\n")?;
+
+        let search = |args: &[&str]| -> Result<(String, String)> {
+            let output = fnr_in(&dir)?
+                .args(["old", "--lookup"])
+                .args(args)
+                .output()?;
+            assert!(output.status.success());
+            Ok((
+                String::from_utf8(output.stdout)?,
+                String::from_utf8(output.stderr)?,
+            ))
+        };
+
+        // Off by default, .fnrignore always applies
+        let (stdout, _) = search(&[])?;
+        assert!(stdout.contains("target/built.txt"));
+        assert!(!stdout.contains("skipped.txt"));
+
+        let (stdout, stderr) = search(&["--respect-gitignore", "--verbose"])?;
+        assert!(!stdout.contains("target/built.txt"));
+        assert!(!stdout.contains("skipped.txt"));
+        assert!(stdout.contains("source.txt"));
+        assert!(stderr.contains("Ignore files: .fnrignore, .gitignore"));
+
+        // Default set in the config file, overridden on the command line
+        std::fs::write(config_dir.join("config.toml"), "respect-gitignore = true\n")?;
+        let (stdout, _) = search(&[])?;
+        assert!(!stdout.contains("target/built.txt"));
+        let (stdout, _) = search(&["--no-respect-gitignore"])?;
+        assert!(stdout.contains("target/built.txt"));
+
+        std::fs::write(config_dir.join("config.toml"), "respect-gitignore = 1\n")?;
+        let output = fnr_in(&dir)?.args(["old", "--lookup"]).output()?;
+        assert_eq!(output.status.code(), Some(2));
+        assert!(str::from_utf8(&output.stderr)?.contains("Invalid config file"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_user_and_extra_ignore_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config_dir = config_dir(&dir);
+        std::fs::create_dir_all(dir.path().join("secret"))?;
+        std::fs::create_dir_all(&config_dir)?;
+        std::fs::write(config_dir.join("ignore"), "*.log\n")?;
+        std::fs::write(dir.path().join(".extra-ignore"), "secret/\n")?;
+        // Takes precedence over the user's ignore file
+        std::fs::write(dir.path().join(".fnrignore"), "!keep.log\n")?;
+        for file in ["app.log", "keep.log", "secret/key.txt", "source.txt"] {
+            std::fs::write(dir.path().join(file), "old\n")?;
+        }
+
+        let output = fnr_in(&dir)?
+            .args(["old", "--lookup", "--verbose", "--ignore-file"])
+            .arg(dir.path().join(".extra-ignore"))
+            .output()?;
+        assert!(output.status.success());
+
+        let stdout = str::from_utf8(&output.stdout)?;
+        assert!(stdout.contains("keep.log"));
+        assert!(stdout.contains("source.txt"));
+        assert!(!stdout.contains("app.log"));
+        assert!(!stdout.contains("key.txt"));
+
+        let stderr = str::from_utf8(&output.stderr)?;
+        assert!(stderr.contains(&config_dir.join("ignore").display().to_string()));
+        assert!(stderr.contains(&dir.path().join(".extra-ignore").display().to_string()));
+
+        // A missing --ignore-file is an error
+        fnr_in(&dir)?
+            .args(["old", "--lookup", "--ignore-file", "missing"])
+            .assert()
+            .code(2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ignore_file_patterns_are_anchored_to_the_search_root() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::create_dir_all(dir.path().join("proj/build"))?;
+        std::fs::create_dir_all(dir.path().join("proj/src/build"))?;
+        std::fs::create_dir_all(dir.path().join("proj/docs"))?;
+        std::fs::write(dir.path().join("proj/build/out.txt"), "old\n")?;
+        std::fs::write(dir.path().join("proj/src/build/code.txt"), "old\n")?;
+        std::fs::write(dir.path().join("proj/docs/notes.md"), "old\n")?;
+        std::fs::write(dir.path().join("proj/docs/keep.md"), "old\n")?;
+        let ignore_file = dir.path().join(".extra-ignore");
+        std::fs::write(&ignore_file, "/build\ndocs/*.md\n!/docs/keep.md\n")?;
+
+        // The same files, wherever the search root is given from
+        let searches = [
+            (dir.path().to_path_buf(), "proj".into()),
+            (dir.path().join("proj"), ".".into()),
+            (dir.path().join("proj/src"), "..".into()),
+            (dir.path().to_path_buf(), dir.path().join("proj")),
+        ];
+        for (current_dir, root) in searches {
+            let output = fnr(&dir)?
+                .current_dir(current_dir)
+                .args(["old", "new"])
+                .arg(&root)
+                .arg("--ignore-file")
+                .arg(&ignore_file)
+                .output()?;
+            assert!(output.status.success());
+
+            let stdout = str::from_utf8(&output.stdout)?;
+            assert!(!stdout.contains("out.txt"), "{root:?}: {stdout}");
+            assert!(!stdout.contains("notes.md"), "{root:?}: {stdout}");
+            assert!(stdout.contains("code.txt"), "{root:?}: {stdout}");
+            assert!(stdout.contains("keep.md"), "{root:?}: {stdout}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_depth_limits() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        for sub in ["a/b", "a/skipped", "c"] {
+            std::fs::create_dir_all(dir.path().join(sub))?;
+        }
+        std::fs::write(dir.path().join(".fnrignore"), "skipped/\n")?;
+        for file in [
+            "top.txt",
+            "a/mid.txt",
+            "a/b/deep.txt",
+            "a/skipped/x.txt",
+            "c/other.txt",
+        ] {
+            std::fs::write(dir.path().join(file), "old\n")?;
+        }
+
+        let search = |args: &[&str]| -> Result<Vec<String>> {
+            let output = fnr_in(&dir)?
+                .args(["old", "--lookup"])
+                .args(args)
+                .output()?;
+            assert!(output.status.success());
+            Ok(String::from_utf8(output.stdout)?
+                .lines()
+                .filter(|line| line.starts_with("./"))
+                .map(String::from)
+                .collect())
+        };
+
+        assert_eq!(search(&["--max-depth", "1"])?, ["./top.txt"]);
+        assert_eq!(
+            search(&["--min-depth", "2"])?,
+            ["./a/b/deep.txt", "./a/mid.txt", "./c/other.txt"]
+        );
+        // Along with --omit and .fnrignore
+        assert_eq!(
+            search(&["--min-depth", "2", "--max-depth", "2", "--omit", "c"])?,
+            ["./a/mid.txt"]
+        );
+
+        fnr_in(&dir)?
+            .args(["old", "--lookup", "--min-depth", "3", "--max-depth", "2"])
+            .assert()
+            .code(2);
+
+        Ok(())
+    }
 }
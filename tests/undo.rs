@@ -0,0 +1,81 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use crate::common::fnr;
+    use anyhow::Result;
+    use std::{fs, str};
+    use tempfile::TempDir;
+
+    fn write_run(dir: &TempDir, pattern: &str, new_pattern: &str) -> Result<()> {
+        fnr(dir)?
+            .arg(pattern)
+            .arg(new_pattern)
+            .arg(dir.path())
+            .arg("--write")
+            .assert()
+            .success();
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_restores_last_run() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file_path = dir.path().join("file.txt");
+        fs::write(&file_path, "old one\nold two\n")?;
+
+        write_run(&dir, "old", "new")?;
+        write_run(&dir, "one", "1")?;
+        assert_eq!(fs::read_to_string(&file_path)?, "new 1\nnew two\n");
+
+        fnr(&dir)?.arg("undo").assert().success();
+        assert_eq!(fs::read_to_string(&file_path)?, "new one\nnew two\n");
+
+        fnr(&dir)?.arg("undo").assert().success();
+        assert_eq!(fs::read_to_string(&file_path)?, "old one\nold two\n");
+
+        // Nothing left to undo
+        fnr(&dir)?.arg("undo").assert().failure();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_refuses_files_modified_after_the_run() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file_path = dir.path().join("file.txt");
+        fs::write(&file_path, "old one\n")?;
+
+        write_run(&dir, "old", "new")?;
+        fs::write(&file_path, "edited by hand\n")?;
+
+        let output = fnr(&dir)?.arg("undo").output()?;
+
+        assert!(output.status.success());
+        assert!(str::from_utf8(&output.stderr)?.contains("file was modified after the run"));
+        assert_eq!(fs::read_to_string(&file_path)?, "edited by hand\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_lists_runs() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::write(dir.path().join("a.txt"), "old\n")?;
+        fs::write(dir.path().join("b.txt"), "old\n")?;
+
+        let output = fnr(&dir)?.arg("history").output()?;
+        assert_eq!(str::from_utf8(&output.stdout)?, "No run recorded yet.\n");
+
+        write_run(&dir, "old", "new")?;
+
+        let output = fnr(&dir)?.arg("history").output()?;
+        let stdout = str::from_utf8(&output.stdout)?;
+
+        assert!(output.status.success());
+        assert!(stdout.contains("old -> new  2 files"));
+
+        Ok(())
+    }
+}